@@ -0,0 +1,1419 @@
+//! Reading a repo's branches and switching between them, for `git-branch-picker`
+//! and anything else that wants to list branches the way it does.
+
+use anyhow::{Context, Result};
+use git2::BranchType;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Will be styled differently in the branch picker,
+/// along with whatever the repo itself says its default branch is.
+const SPECIAL_BRANCHES: [&str; 2] = ["main", "master"];
+
+/// Upper bound on how many commits are walked per branch when working out its age
+/// (and its `unique_commits`), so a long-lived branch can't stall startup.
+pub const BRANCH_AGE_WALK_LIMIT: usize = 1000;
+
+/// A row of the table: usually a branch, but tags get listed the same way.
+#[derive(Debug)]
+pub struct Branch {
+    /// For display only. Starts out the same as `full_name`,
+    /// the picker then shortens it with the configured replacements.
+    pub name: String,
+    /// The branch's real name, e.g. for passing to `git checkout`.
+    pub full_name: String,
+    pub kind: RefKind,
+    /// For remote-tracking branches, which remote they track.
+    pub remote: Option<String>,
+    /// For local branches, the branch they track, e.g. `origin/foo`.
+    pub upstream: Option<String>,
+    /// For local branches, what `git branch --edit-description` said about it.
+    pub description: Option<String>,
+    /// The commit the branch points at, and when it was made (for sorting by).
+    pub tip: Option<(git2::Oid, git2::Time)>,
+    /// If there's no `tip` because the ref's broken or points at something other
+    /// than a commit, why not.
+    pub broken: Option<String>,
+    /// Whether `last_commit` and `age` have been filled in yet, see `load_details`.
+    pub loaded: bool,
+    pub last_commit: Option<Commit>,
+    /// When the oldest commit unique to this branch (relative to main/master) was made.
+    /// If `unique_commits` reached `BRANCH_AGE_WALK_LIMIT`, that's only the oldest one
+    /// counted, and the branch is older still.
+    pub age: Option<CommitTime>,
+    /// How many commits are on this branch but not on main/master,
+    /// counting no higher than `BRANCH_AGE_WALK_LIMIT`.
+    pub unique_commits: Option<usize>,
+    /// Commits ahead of and behind the upstream branch, if there is one.
+    pub ahead_behind: Option<(usize, usize)>,
+    /// Is this the branch HEAD points at?
+    pub is_current: bool,
+    /// Is everything on the branch already reachable from HEAD? Never true for
+    /// the current branch itself, or for tags. Filled in along with `last_commit`.
+    pub merged: bool,
+    /// If the branch is checked out in another worktree, where that is.
+    pub worktree: Option<std::path::PathBuf>,
+    /// Its open pull request on GitHub. Left empty by `read_branches`, see `Repo::set_pull_requests`.
+    pub pull_request: Option<PullRequest>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefKind {
+    Local,
+    Remote,
+    Tag,
+}
+
+#[derive(Debug)]
+pub struct Commit {
+    pub msg: String,
+    pub author: String,
+    /// The first 7 hex digits of the commit id.
+    pub short_id: String,
+    pub time: CommitTime,
+}
+
+impl Commit {
+    /// `max_msg_width` is in columns, wider subjects get cut short with an ellipsis.
+    pub fn new(c: &git2::Commit, max_msg_width: usize, date_format: &str) -> Self {
+        Self {
+            time: CommitTime::new_or_blank(c.time(), date_format),
+            msg: truncate(&commit_subject(c), max_msg_width),
+            author: String::from_utf8_lossy(c.author().name_bytes()).into_owned(),
+            short_id: c.id().to_string()[..7].to_owned(),
+        }
+    }
+}
+
+/// A commit's timestamp, already formatted both ways the date column can show it.
+#[derive(Debug)]
+pub struct CommitTime {
+    /// For sorting.
+    pub raw: git2::Time,
+    /// e.g. "3 days, 2 hours ago".
+    pub relative: String,
+    /// e.g. "2024-06-01 14:32", in the local timezone.
+    pub absolute: String,
+}
+
+/// How `CommitTime::absolute` is written unless the caller wants something else.
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+impl CommitTime {
+    /// `date_format` is a strftime-style format for `absolute`, e.g. `DEFAULT_DATE_FORMAT`.
+    pub fn new(raw: git2::Time, date_format: &str) -> Result<Self> {
+        let zoned =
+            jiff::Timestamp::from_second(raw.seconds())?.to_zoned(jiff::tz::TimeZone::system());
+        let absolute = jiff::fmt::strtime::format(date_format, &zoned)?;
+        Ok(Self {
+            raw,
+            relative: human_friendly_time_since(raw, jiff::Timestamp::now())?,
+            absolute,
+        })
+    }
+
+    /// Like `new`, but with nothing to show rather than an error if the time can't be written,
+    /// e.g. a corrupt commit's timestamp that's out of jiff's range.
+    pub fn new_or_blank(raw: git2::Time, date_format: &str) -> Self {
+        Self::new(raw, date_format).unwrap_or_else(|_| Self {
+            raw,
+            relative: String::new(),
+            absolute: String::new(),
+        })
+    }
+
+    /// Brings `relative` up to date with the current time. Returns whether it changed.
+    pub fn refresh(&mut self) -> bool {
+        match human_friendly_time_since(self.raw, jiff::Timestamp::now()) {
+            Ok(relative) if relative != self.relative => {
+                self.relative = relative;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn show(&self, style: DateStyle) -> &str {
+        match style {
+            DateStyle::Relative => &self.relative,
+            DateStyle::Absolute => &self.absolute,
+        }
+    }
+}
+
+/// How the date column writes times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateStyle {
+    Relative,
+    Absolute,
+}
+
+impl DateStyle {
+    pub fn toggle(self) -> Self {
+        match self {
+            Self::Relative => Self::Absolute,
+            Self::Absolute => Self::Relative,
+        }
+    }
+}
+
+/// One line of the preview pane.
+#[derive(Debug)]
+pub struct PreviewCommit {
+    /// Abbreviated.
+    pub hash: String,
+    pub msg: String,
+    pub time: String,
+}
+
+/// Everything about a commit, for looking at one on its own.
+#[derive(Debug)]
+pub struct FullCommit {
+    /// In full.
+    pub hash: String,
+    /// e.g. `Jo Bloggs <jo@example.com>`.
+    pub author: String,
+    pub time: CommitTime,
+    /// Subject and body, as written.
+    pub message: String,
+}
+
+/// What order the branches are listed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortMode {
+    /// Most recently committed to first.
+    Recent,
+    Alphabetical,
+    /// Furthest ahead of upstream first, then branches without an upstream.
+    MostAhead,
+    /// Most recently switched to with the picker first, then the rest by `Recent`.
+    LastUsed,
+}
+
+impl SortMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Recent => Self::Alphabetical,
+            Self::Alphabetical => Self::MostAhead,
+            Self::MostAhead => Self::LastUsed,
+            Self::LastUsed => Self::Recent,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Recent => "most recent",
+            Self::Alphabetical => "name",
+            Self::MostAhead => "most ahead",
+            Self::LastUsed => "last used",
+        }
+    }
+
+    pub fn sort(self, branches: &mut [Branch], recently_used: &[String]) {
+        // Ties go by name, so that e.g. branches a script made in the same second
+        // come out in the same order every run.
+        let by_name = |x: &Branch, y: &Branch| x.full_name.cmp(&y.full_name);
+        match self {
+            Self::Recent => branches.sort_by(|x, y| {
+                let when = |b: &Branch| b.tip.map(|(_, time)| time.seconds());
+                when(y).cmp(&when(x)).then_with(|| by_name(x, y))
+            }),
+            Self::Alphabetical => branches.sort_by(by_name),
+            Self::MostAhead => branches.sort_by(|x, y| {
+                let ahead = |b: &Branch| b.ahead_behind.map(|(ahead, _)| ahead);
+                ahead(y).cmp(&ahead(x)).then_with(|| by_name(x, y))
+            }),
+            Self::LastUsed => {
+                Self::Recent.sort(branches, recently_used);
+                // Stable, so the branches that haven't been used stay in `Recent` order.
+                branches.sort_by_key(|b| {
+                    recently_used
+                        .iter()
+                        .position(|name| *name == b.full_name)
+                        .unwrap_or(usize::MAX)
+                });
+            }
+        }
+    }
+}
+
+impl Branch {
+    /// The full ref, e.g. `refs/heads/foo`, which unlike `full_name` can't be mistaken
+    /// for a tag or remote branch with the same name.
+    pub fn refname(&self) -> String {
+        match self.kind {
+            RefKind::Local => format!("refs/heads/{}", self.full_name),
+            RefKind::Remote => format!("refs/remotes/{}", self.full_name),
+            RefKind::Tag => format!("refs/tags/{}", self.full_name),
+        }
+    }
+
+    /// Fills in `last_commit` and `age`. They're left out to begin with,
+    /// because in a repo with thousands of branches reading them all would hold up startup.
+    /// The picker gets them from a `DetailsLoader` instead.
+    pub fn load_details(
+        &mut self,
+        repo: &git2::Repository,
+        base: Option<git2::Oid>,
+        head: Option<git2::Oid>,
+        max_msg_width: usize,
+        date_format: &str,
+    ) {
+        if !self.loaded {
+            let tip = self.tip.map(|(tip, _)| tip);
+            let details =
+                Details::read(repo, tip, self.kind, base, head, max_msg_width, date_format);
+            self.set_details(details);
+        }
+    }
+
+    pub fn set_details(&mut self, details: Details) {
+        self.loaded = true;
+        self.last_commit = details.last_commit;
+        self.age = details.age;
+        self.unique_commits = details.unique_commits;
+        self.merged = details.merged && !self.is_current;
+    }
+
+    /// The first path segment of the name, e.g. `feature` for `feature/login`
+    /// (or for `origin/feature/login`, or `o/feature/login` if the remote's shortened).
+    pub fn namespace(&self) -> Option<&str> {
+        let name = match &self.remote {
+            Some(remote) => match self.name.strip_prefix(remote.as_str()) {
+                Some(rest) => rest.strip_prefix('/')?,
+                None => self.name.split_once('/')?.1,
+            },
+            None => &self.name,
+        };
+        name.split_once('/').map(|(namespace, _)| namespace)
+    }
+}
+
+/// Why a checkout didn't happen.
+#[derive(Debug)]
+pub enum CheckoutError {
+    /// Switching would have overwritten uncommitted changes to these paths.
+    Conflicts {
+        branch: String,
+        paths: Vec<String>,
+    },
+    Git(git2::Error),
+}
+
+impl std::fmt::Display for CheckoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Conflicts { branch, paths } => write!(
+                f,
+                "checking out {branch} would overwrite your local changes to:\n  {}",
+                paths.join("\n  ")
+            ),
+            Self::Git(e) => write!(f, "checkout failed: {}", e.message()),
+        }
+    }
+}
+
+impl std::error::Error for CheckoutError {}
+
+impl CheckoutError {
+    /// What to do about it, for the failures that have an obvious fix.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::Conflicts { .. } => Some("commit or stash your changes first"),
+            Self::Git(e) => match e.code() {
+                git2::ErrorCode::NotFound => {
+                    Some("it may have been deleted or renamed since the branches were listed")
+                }
+                git2::ErrorCode::Locked => Some(
+                    "another git process seems to be running in this repository, \
+                     if not then remove the .git/index.lock it left behind",
+                ),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl From<git2::Error> for CheckoutError {
+    fn from(e: git2::Error) -> Self {
+        Self::Git(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct Repo {
+    pub branches: Vec<Branch>,
+    pub root: String,
+    /// The repo's .git directory, for reopening it when the picker needs to look something up.
+    pub git_dir: std::path::PathBuf,
+    /// `SPECIAL_BRANCHES` (or whatever `read_branches_with` was given instead)
+    /// merged with the repo's configured default branches.
+    pub special_branches: Vec<String>,
+    /// Names of the configured remotes.
+    pub remotes: Vec<String>,
+    /// How many branches the `read_branches_excluding` patterns left out.
+    pub excluded: usize,
+    /// How many branches `retain_authored_by` left out.
+    pub by_others: usize,
+    /// What branch ages are measured against, see `age_base`.
+    pub age_base: Option<git2::Oid>,
+    /// The commit HEAD was at when the branches were read, for `Details::merged`.
+    pub head: Option<git2::Oid>,
+    /// Local branches recently switched to, most recent first, for `SortMode::LastUsed`.
+    /// Left empty by `read_branches`, the picker fills it in from its history.
+    pub recently_used: Vec<String>,
+}
+
+/// Switches to a ref (e.g. `refs/heads/foo`) the way `git checkout` would: local branches
+/// become HEAD, anything else (e.g. a tag) leaves HEAD detached at its commit.
+/// If that would overwrite uncommitted changes, nothing is touched.
+///
+/// It takes the full ref name because short ones are ambiguous: with a branch and a tag
+/// both called `foo`, git's lookup order would pick the tag.
+pub fn checkout(git: &git2::Repository, refname: &str) -> Result<(), CheckoutError> {
+    let reference = git.find_reference(refname)?;
+    let tree = reference.peel_to_tree()?;
+    let name = reference.shorthand().unwrap_or(refname);
+    checkout_tree_safely(git, &tree, name)?;
+    // Only move HEAD once the working tree is safely switched over.
+    git.set_head(refname)?;
+    Ok(())
+}
+
+/// The commit HEAD points at, if it points at one yet.
+pub fn head_commit(git: &git2::Repository) -> Option<git2::Oid> {
+    Some(git.head().ok()?.peel_to_commit().ok()?.id())
+}
+
+/// Runs the repo's `post-checkout` hook, if it has one, the way `git checkout` does after
+/// switching away from `old_head`. `checkout` and friends don't, since libgit2 never runs hooks.
+/// As with git, a failing hook doesn't undo the checkout, but it is an error.
+pub fn run_post_checkout_hook(git: &git2::Repository, old_head: Option<git2::Oid>) -> Result<()> {
+    let workdir = git.workdir().unwrap_or(git.path());
+    let hooks_dir = match git.config()?.get_path("core.hooksPath") {
+        // Hooks run from the top of the working tree, so that's what it's relative to.
+        Ok(path) => workdir.join(path),
+        Err(_) => git.commondir().join("hooks"),
+    };
+    let hook = hooks_dir.join("post-checkout");
+    if !is_executable(&hook) {
+        return Ok(());
+    }
+    let new_head = head_commit(git).unwrap_or_else(git2::Oid::zero);
+    let old_head = old_head.unwrap_or_else(git2::Oid::zero);
+    let status = std::process::Command::new(&hook)
+        // The 1 means it was a branch checkout, rather than of some files.
+        .args([old_head.to_string(), new_head.to_string(), "1".to_owned()])
+        .current_dir(workdir)
+        .stdin(std::process::Stdio::null())
+        // Like git, keep stdout for the picker's own output, e.g. `--print`'s.
+        .stdout(std::io::stderr())
+        .status()
+        .with_context(|| format!("couldn't run {}", hook.display()))?;
+    if !status.success() {
+        anyhow::bail!("the post-checkout hook failed ({status})");
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &std::path::Path) -> bool {
+    path.is_file()
+}
+
+/// Switches the working tree over to `tree`, leaving HEAD for the caller to move.
+/// If that would overwrite uncommitted changes, nothing is touched.
+fn checkout_tree_safely(
+    git: &git2::Repository,
+    tree: &git2::Tree,
+    name: &str,
+) -> Result<(), CheckoutError> {
+    let mut conflicts = Vec::new();
+    let mut opts = git2::build::CheckoutBuilder::new();
+    opts.safe()
+        .notify_on(git2::CheckoutNotificationType::CONFLICT)
+        .notify(|_, path, _, _, _| {
+            if let Some(path) = path {
+                conflicts.push(path.display().to_string());
+            }
+            true
+        });
+    let checked_out = git.checkout_tree(tree.as_object(), Some(&mut opts));
+    drop(opts);
+    if !conflicts.is_empty() {
+        return Err(CheckoutError::Conflicts {
+            branch: name.to_owned(),
+            paths: conflicts,
+        });
+    }
+    checked_out?;
+    Ok(())
+}
+
+impl Repo {
+    pub fn open(&self) -> Result<git2::Repository, git2::Error> {
+        git2::Repository::open(&self.git_dir)
+    }
+
+    /// Switches to the local branch called `name`, see `checkout`.
+    pub fn checkout_branch(&self, name: &str) -> Result<(), CheckoutError> {
+        checkout(&self.open()?, &format!("refs/heads/{name}"))
+    }
+
+    /// Detaches HEAD at the tag called `name`, see `checkout`.
+    pub fn checkout_tag(&self, name: &str) -> Result<(), CheckoutError> {
+        checkout(&self.open()?, &format!("refs/tags/{name}"))
+    }
+
+    /// Like `git checkout --detach`: HEAD points straight at the commit, and no branch moves.
+    pub fn checkout_detached(&self, commit: git2::Oid) -> Result<(), CheckoutError> {
+        let git = self.open()?;
+        let tree = git.find_commit(commit)?.tree()?;
+        checkout_tree_safely(&git, &tree, &commit.to_string())?;
+        git.set_head_detached(commit)?;
+        Ok(())
+    }
+
+    /// Like `git switch foo` when only `origin/foo` exists: makes a local branch from a
+    /// remote-tracking one, sets it to track that, and switches to it. If there's already
+    /// a local branch called that, it switches to that one instead, without moving it.
+    /// Returns the local branch's name, and whether it had to be made.
+    pub fn checkout_tracking(
+        &self,
+        remote_branch: &str,
+        remote: &str,
+    ) -> Result<(String, bool), CheckoutError> {
+        let local_name = remote_branch
+            .strip_prefix(remote)
+            .and_then(|name| name.strip_prefix('/'))
+            .unwrap_or(remote_branch);
+        let git = self.open()?;
+        if git.find_branch(local_name, BranchType::Local).is_ok() {
+            self.checkout_branch(local_name)?;
+            return Ok((local_name.to_owned(), false));
+        }
+        let commit = git
+            .find_branch(remote_branch, BranchType::Remote)?
+            .get()
+            .peel_to_commit()?;
+        let mut local = git.branch(local_name, &commit, false)?;
+        local.set_upstream(Some(remote_branch))?;
+        if let Err(e) = self.checkout_branch(local_name) {
+            // Don't leave a half-made branch behind.
+            local.delete()?;
+            return Err(e);
+        }
+        Ok((local_name.to_owned(), true))
+    }
+
+    /// Tracked files with uncommitted changes, staged or not.
+    pub fn dirty_paths(&self) -> Result<Vec<String>, git2::Error> {
+        let git = self.open()?;
+        let mut opts = git2::StatusOptions::new();
+        opts.include_untracked(false).include_ignored(false);
+        let statuses = git.statuses(Some(&mut opts))?;
+        Ok(statuses
+            .iter()
+            .filter(|entry| entry.status() != git2::Status::CURRENT)
+            .filter_map(|entry| entry.path().map(ToOwned::to_owned))
+            .collect())
+    }
+
+    /// Like `git pull --ff-only` for this checked-out branch: fetches its upstream,
+    /// then moves the branch up to it, unless they've diverged. Without an upstream it does nothing.
+    /// Returns what happened, to tell the user.
+    pub fn pull_fast_forward(&self, name: &str) -> Result<String> {
+        let git = self.open()?;
+        let refname = format!("refs/heads/{name}");
+        let Ok(remote) = git.branch_upstream_remote(&refname) else {
+            return Ok(format!(
+                "'{name}' has no upstream, so there's nothing to pull"
+            ));
+        };
+        let remote = remote.as_str().context("the remote's name isn't UTF-8")?;
+        fetch_remote(&self.git_dir, remote)?;
+
+        let mut branch = git.find_branch(name, BranchType::Local)?;
+        let upstream = branch.upstream()?;
+        let upstream_name = upstream.name()?.unwrap_or_default().to_owned();
+        let annotated = git.reference_to_annotated_commit(upstream.get())?;
+        let (analysis, _) = git.merge_analysis(&[&annotated])?;
+        if analysis.is_up_to_date() {
+            return Ok(format!(
+                "'{name}' is already up to date with '{upstream_name}'"
+            ));
+        }
+        if !analysis.is_fast_forward() {
+            anyhow::bail!("it's diverged from '{upstream_name}', it needs a merge or rebase");
+        }
+        let target = upstream.get().peel_to_commit()?;
+        git.checkout_tree(
+            target.as_object(),
+            Some(git2::build::CheckoutBuilder::new().safe()),
+        )?;
+        branch.get_mut().set_target(
+            target.id(),
+            &format!("pull: fast-forward to {upstream_name}"),
+        )?;
+        Ok(format!(
+            "Fast-forwarded '{name}' to '{upstream_name}' ({})",
+            &target.id().to_string()[..7]
+        ))
+    }
+
+    pub fn stash_changes(&self) -> Result<(), git2::Error> {
+        let mut git = self.open()?;
+        let signature = git.signature()?;
+        git.stash_save(
+            &signature,
+            "git-branch-picker: stashed before switching branches",
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Is everything on this local branch already reachable from HEAD?
+    pub fn is_merged(&self, name: &str) -> Result<bool, git2::Error> {
+        let git = self.open()?;
+        let head = git.head()?.peel_to_commit()?.id();
+        let tip = git
+            .find_branch(name, BranchType::Local)?
+            .get()
+            .peel_to_commit()?
+            .id();
+        is_merged_into(&git, tip, head)
+    }
+
+    pub fn delete_branch(&self, name: &str) -> Result<(), git2::Error> {
+        let git = self.open()?;
+        git.find_branch(name, BranchType::Local)?.delete()?;
+        Ok(())
+    }
+
+    /// Like `git branch -m`, or `-M` if `force`. HEAD follows the branch if it's checked out.
+    pub fn rename_branch(
+        &self,
+        name: &str,
+        new_name: &str,
+        force: bool,
+    ) -> Result<(), git2::Error> {
+        let git = self.open()?;
+        git.find_branch(name, BranchType::Local)?
+            .rename(new_name, force)?;
+        Ok(())
+    }
+
+    pub fn create_branch_at_head(&self, name: &str) -> Result<()> {
+        let git = self.open()?;
+        let head = git.head()?.peel_to_commit()?;
+        git.branch(name, &head, false)?;
+        Ok(())
+    }
+
+    /// Up to `limit` commits reachable from the ref (see `Branch::refname`), newest first.
+    pub fn recent_commits(&self, refname: &str, limit: usize) -> Result<Vec<PreviewCommit>> {
+        let git = self.open()?;
+        let tip = git.find_reference(refname)?.peel_to_commit()?;
+        let mut walk = git.revwalk()?;
+        walk.push(tip.id())?;
+        let mut commits = Vec::new();
+        for oid in walk.take(limit) {
+            let commit = git.find_commit(oid?)?;
+            commits.push(PreviewCommit {
+                hash: commit.id().to_string()[..7].to_owned(),
+                msg: commit_subject(&commit),
+                time: human_friendly_time_since(commit.time(), jiff::Timestamp::now())?,
+            });
+        }
+        Ok(commits)
+    }
+
+    pub fn full_commit(&self, id: git2::Oid, date_format: &str) -> Result<FullCommit> {
+        let git = self.open()?;
+        let commit = git.find_commit(id)?;
+        let author = commit.author();
+        Ok(FullCommit {
+            hash: id.to_string(),
+            author: format!(
+                "{} <{}>",
+                String::from_utf8_lossy(author.name_bytes()),
+                String::from_utf8_lossy(author.email_bytes())
+            ),
+            time: CommitTime::new(commit.time(), date_format)?,
+            message: String::from_utf8_lossy(commit.message_bytes())
+                .trim_end()
+                .to_owned(),
+        })
+    }
+
+    /// Looks up a local branch that was made after the picker started, e.g. by `create_branch_at_head`.
+    pub fn read_local_branch(&self, name: &str) -> Result<Branch> {
+        let git = self.open()?;
+        let branch = git.find_branch(name, BranchType::Local)?;
+        read_branch(&git, &self.remotes, &branch, BranchType::Local)
+    }
+
+    /// The remote a plain `git fetch` would use: the current branch's upstream's,
+    /// otherwise `origin`, otherwise whichever remote there is.
+    pub fn default_remote(&self) -> Option<String> {
+        let upstream_remote = self.open().ok().and_then(|git| {
+            let head = git.head().ok()?;
+            let remote = git.branch_upstream_remote(head.name()?).ok()?;
+            remote.as_str().map(String::from)
+        });
+        upstream_remote
+            .or_else(|| self.remotes.iter().find(|r| *r == "origin").cloned())
+            .or_else(|| self.remotes.first().cloned())
+    }
+
+    pub fn is_special(&self, branch: &Branch) -> bool {
+        self.special_branches.contains(&branch.full_name)
+    }
+
+    /// Leaves out branches whose tip commit wasn't authored by this email address
+    /// (ignoring case). Tags and broken branches are kept, they don't have an author to go by.
+    pub fn retain_authored_by(&mut self, repo: &git2::Repository, email: &str) {
+        let before = self.branches.len();
+        self.branches.retain(|branch| {
+            let Some((tip, _)) = branch.tip else {
+                return true;
+            };
+            if branch.kind == RefKind::Tag {
+                return true;
+            }
+            repo.find_commit(tip).is_ok_and(|commit| {
+                String::from_utf8_lossy(commit.author().email_bytes()).eq_ignore_ascii_case(email)
+            })
+        });
+        self.by_others += before - self.branches.len();
+    }
+
+    /// Fills in each branch's `pull_request` from `github_pull_requests`.
+    /// Remote-tracking branches are matched by their name on the remote.
+    pub fn set_pull_requests(
+        &mut self,
+        pull_requests: &std::collections::HashMap<String, PullRequest>,
+    ) {
+        for branch in &mut self.branches {
+            let head = match (branch.kind, &branch.remote) {
+                (RefKind::Local, _) => Some(branch.full_name.as_str()),
+                (RefKind::Remote, Some(remote)) => branch
+                    .full_name
+                    .strip_prefix(remote.as_str())
+                    .and_then(|name| name.strip_prefix('/')),
+                _ => None,
+            };
+            branch.pull_request = head.and_then(|head| pull_requests.get(head)).cloned();
+        }
+    }
+
+    /// Sorts the branches, then moves the special ones to the top if `pin_special`.
+    pub fn sort_branches(&mut self, sort_mode: SortMode, pin_special: bool) {
+        sort_mode.sort(&mut self.branches, &self.recently_used);
+        if pin_special {
+            let special = &self.special_branches;
+            // Stable, so the special branches stay in sort order amongst themselves.
+            self.branches
+                .sort_by_key(|b| !special.contains(&b.full_name));
+        }
+    }
+}
+
+/// The slower parts of a `Branch` to work out, see `Branch::load_details`.
+#[derive(Debug)]
+pub struct Details {
+    pub last_commit: Option<Commit>,
+    pub age: Option<CommitTime>,
+    pub unique_commits: Option<usize>,
+    /// Whether `head` can reach the tip. `Branch::set_details` leaves this out for the current branch.
+    pub merged: bool,
+}
+
+impl Details {
+    pub fn read(
+        repo: &git2::Repository,
+        tip: Option<git2::Oid>,
+        kind: RefKind,
+        base: Option<git2::Oid>,
+        head: Option<git2::Oid>,
+        max_msg_width: usize,
+        date_format: &str,
+    ) -> Self {
+        let Some(commit) = tip.and_then(|tip| repo.find_commit(tip).ok()) else {
+            return Self {
+                last_commit: None,
+                age: None,
+                unique_commits: None,
+                merged: false,
+            };
+        };
+        let (unique, merged) = match kind {
+            RefKind::Tag => (None, false),
+            RefKind::Local | RefKind::Remote => (
+                base.and_then(|base| unique_commits(repo, commit.id(), base)),
+                head.is_some_and(|head| is_merged_into(repo, commit.id(), head).unwrap_or(false)),
+            ),
+        };
+        Self {
+            last_commit: Some(Commit::new(&commit, max_msg_width, date_format)),
+            age: unique
+                .and_then(|(_, oldest)| oldest)
+                .map(|time| CommitTime::new_or_blank(time, date_format)),
+            unique_commits: unique.map(|(count, _)| count),
+            merged,
+        }
+    }
+}
+
+/// Like `git fetch <remote>`. Credentials come from the SSH agent or git's credential helpers,
+/// there's no prompting for them since the picker has the terminal.
+pub fn fetch_remote(git_dir: &std::path::Path, name: &str) -> Result<(), git2::Error> {
+    let git = git2::Repository::open(git_dir)?;
+    let git_config = git.config()?;
+    let mut remote = git.find_remote(name)?;
+    // libgit2 keeps asking for as long as the credentials it gets are rejected,
+    // so try each kind once and then give up.
+    let mut tried = git2::CredentialType::empty();
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(|url, username, allowed| {
+        let untried = allowed - tried;
+        if untried.contains(git2::CredentialType::SSH_KEY) {
+            tried |= git2::CredentialType::SSH_KEY;
+            git2::Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if untried.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            tried |= git2::CredentialType::USER_PASS_PLAINTEXT;
+            git2::Cred::credential_helper(&git_config, url, username)
+        } else if untried.contains(git2::CredentialType::DEFAULT) {
+            tried |= git2::CredentialType::DEFAULT;
+            git2::Cred::default()
+        } else {
+            Err(git2::Error::from_str("no usable credentials"))
+        }
+    });
+    let mut options = git2::FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    remote.fetch::<&str>(&[], Some(&mut options), None)
+}
+
+/// A pull request, as `gh pr list --json` describes it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequest {
+    pub number: u64,
+    /// e.g. `OPEN`.
+    pub state: String,
+    /// The branch it's from.
+    #[serde(skip_serializing)]
+    pub head_ref_name: String,
+    /// Whether that branch is in someone's fork rather than this repo.
+    #[serde(skip_serializing)]
+    pub is_cross_repository: bool,
+}
+
+/// The repo's open pull requests, by the branch they're from, using the `gh` CLI from `dir`.
+/// None if `gh` isn't installed, isn't logged in, or can't tell which GitHub repo this is.
+pub fn github_pull_requests(
+    dir: &std::path::Path,
+) -> Option<std::collections::HashMap<String, PullRequest>> {
+    let output = std::process::Command::new("gh")
+        .args(["pr", "list", "--limit", "1000"])
+        .args(["--json", "headRefName,isCrossRepository,number,state"])
+        .current_dir(dir)
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let listed: Vec<PullRequest> = serde_json::from_slice(&output.stdout).ok()?;
+    let mut by_head = std::collections::HashMap::new();
+    // A fork's `main` or `fix` isn't the local branch of that name.
+    for pr in listed.into_iter().filter(|pr| !pr.is_cross_repository) {
+        // A branch can have PRs open into several bases, gh lists the newest first.
+        by_head.entry(pr.head_ref_name.clone()).or_insert(pr);
+    }
+    Some(by_head)
+}
+
+const TIME_PRINTER: jiff::fmt::friendly::SpanPrinter = jiff::fmt::friendly::SpanPrinter::new()
+    .spacing(jiff::fmt::friendly::Spacing::BetweenUnitsAndDesignators)
+    .comma_after_designator(true)
+    .designator(jiff::fmt::friendly::Designator::Verbose);
+
+/// How long before `now` the commit was made. `t`'s seconds are already since the epoch,
+/// whatever offset the committer was at, and both ends are compared in UTC
+/// so no DST change can add or lose an hour in between.
+pub fn human_friendly_time_since(t: git2::Time, now: jiff::Timestamp) -> Result<String> {
+    let in_utc = |ts: jiff::Timestamp| ts.to_zoned(jiff::tz::TimeZone::UTC).datetime();
+    let committed_at = jiff::Timestamp::from_second(t.seconds())?;
+    relative_time(in_utc(committed_at), in_utc(now))
+}
+
+/// "3 days ago" if `then` is before `now`, "in 3 days" if it's after
+/// (e.g. a commit from a machine with a skewed clock),
+/// or "just now" if they're within a minute of each other.
+fn relative_time(then: jiff::civil::DateTime, now: jiff::civil::DateTime) -> Result<String> {
+    let since = (now - then).round(
+        jiff::SpanRound::new()
+            .smallest(jiff::Unit::Minute)
+            .days_are_24_hours(),
+    )?;
+    let printed = TIME_PRINTER.span_to_string(&since.abs());
+    Ok(match since.signum() {
+        0 => "just now".to_owned(),
+        1 => format!("{printed} ago"),
+        _ => format!("in {printed}"),
+    })
+}
+
+/// The first line of a commit's message, trimmed and with tabs etc. turned into spaces
+/// so it can't throw the table's columns out of line.
+/// Some tooling makes commits with no message at all, so those get a placeholder.
+pub fn commit_subject(c: &git2::Commit) -> String {
+    c.message()
+        .and_then(|msg| msg.lines().next())
+        .map(str::trim)
+        .filter(|subject| !subject.is_empty())
+        .unwrap_or("<empty>")
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+/// Cuts `s` down to `max` columns wide, ending in "…" if anything was cut.
+pub fn truncate(s: &str, max: usize) -> String {
+    if s.width() <= max {
+        return s.to_owned();
+    }
+    let mut width = 0;
+    let mut truncated: String = s
+        .chars()
+        .take_while(|c| {
+            width += c.width().unwrap_or(0);
+            width < max
+        })
+        .collect();
+    truncated.push('…');
+    truncated
+}
+
+/// How far a local branch has drifted from the branch it tracks.
+fn ahead_behind(
+    repo: &git2::Repository,
+    branch: &git2::Branch,
+    upstream: &git2::Branch,
+) -> Option<(usize, usize)> {
+    let local = branch.get().target()?;
+    let upstream = upstream.get().target()?;
+    repo.graph_ahead_behind(local, upstream).ok()
+}
+
+/// How big is this branch, and when did it diverge?
+/// Walks back from `tip` (hiding everything reachable from `base`) and returns how many
+/// commits only this branch has, and the time of the oldest one if there are any.
+/// Both stop at `BRANCH_AGE_WALK_LIMIT` commits.
+fn unique_commits(
+    repo: &git2::Repository,
+    tip: git2::Oid,
+    base: git2::Oid,
+) -> Option<(usize, Option<git2::Time>)> {
+    let mut walk = repo.revwalk().ok()?;
+    walk.set_sorting(git2::Sort::TIME).ok()?;
+    walk.push(tip).ok()?;
+    walk.hide(base).ok()?;
+    let mut count = 0;
+    let mut oldest = None;
+    for id in walk.take(BRANCH_AGE_WALK_LIMIT).filter_map(Result::ok) {
+        count += 1;
+        oldest = Some(id);
+    }
+    let oldest_time = oldest.and_then(|id| Some(repo.find_commit(id).ok()?.time()));
+    Some((count, oldest_time))
+}
+
+/// The repo's own idea of its default branch (`init.defaultBranch` and origin's HEAD),
+/// followed by the ones the caller asked for.
+fn special_branches(repo: &git2::Repository, asked_for: &[String]) -> Vec<String> {
+    let configured = repo
+        .config()
+        .ok()
+        .and_then(|c| c.get_string("init.defaultBranch").ok());
+    let origin_head = repo
+        .find_reference("refs/remotes/origin/HEAD")
+        .ok()
+        .and_then(|r| {
+            r.symbolic_target()?
+                .strip_prefix("refs/remotes/origin/")
+                .map(ToOwned::to_owned)
+        });
+    let mut special = Vec::new();
+    for name in [origin_head, configured]
+        .into_iter()
+        .flatten()
+        .chain(asked_for.iter().cloned())
+    {
+        if !special.contains(&name) {
+            special.push(name);
+        }
+    }
+    special
+}
+
+fn is_merged_into(
+    repo: &git2::Repository,
+    tip: git2::Oid,
+    head: git2::Oid,
+) -> Result<bool, git2::Error> {
+    Ok(tip == head || repo.graph_descendant_of(head, tip)?)
+}
+
+/// Every local and remote-tracking branch, and every tag, most recently committed to first.
+/// Their `last_commit` and `age` aren't loaded yet, see `Branch::load_details`.
+pub fn read_branches(repo: &git2::Repository) -> Result<Repo> {
+    read_branches_excluding(repo, &[])
+}
+
+/// Like `read_branches`, but leaving out branches matching any of these patterns.
+pub fn read_branches_excluding(repo: &git2::Repository, exclude: &[glob::Pattern]) -> Result<Repo> {
+    read_branches_with(repo, exclude, &SPECIAL_BRANCHES.map(String::from))
+}
+
+/// Like `read_branches_excluding`, with `special` in place of `SPECIAL_BRANCHES`,
+/// e.g. for a team whose main line is called `develop`.
+pub fn read_branches_with(
+    repo: &git2::Repository,
+    exclude: &[glob::Pattern],
+    special: &[String],
+) -> Result<Repo> {
+    let special_branches = special_branches(repo, special);
+    let base = age_base(repo, &special_branches);
+    let remotes: Vec<String> = repo.remotes()?.iter().flatten().map(String::from).collect();
+    let branches = repo.branches(None)?;
+    let worktrees = other_worktrees(repo);
+    let mut out_branches = Vec::new();
+    let mut excluded = 0;
+    for branch in branches {
+        let (branch, branch_type) = branch?;
+        // Skip `origin/HEAD`, it's just an alias for one of origin's other branches.
+        if branch.get().symbolic_target().is_some() {
+            continue;
+        }
+        if is_excluded(&branch, &remotes, exclude) {
+            excluded += 1;
+            continue;
+        }
+        let mut branch = read_branch(repo, &remotes, &branch, branch_type)?;
+        if branch_type == BranchType::Local {
+            branch.worktree = worktrees.get(&branch.full_name).cloned();
+        }
+        out_branches.push(branch);
+    }
+    out_branches.extend(read_tags(repo)?);
+
+    // In a linked worktree, `path()` is somewhere under the main repo's .git.
+    let root = match repo.workdir() {
+        Some(workdir) => workdir.components().collect::<std::path::PathBuf>(),
+        None => repo.path().parent().unwrap().to_owned(),
+    };
+    let root = root.display().to_string();
+    let home = std::env::var("HOME");
+    let root = if let Ok(home) = home {
+        if let Some(relative_to_homedir) = root.strip_prefix(&home) {
+            format!("~{relative_to_homedir}")
+        } else {
+            root
+        }
+    } else {
+        root
+    };
+    let mut out = Repo {
+        branches: out_branches,
+        root,
+        git_dir: repo.path().to_owned(),
+        special_branches,
+        remotes,
+        excluded,
+        by_others: 0,
+        age_base: base,
+        head: repo.head().ok().and_then(|head| head.target()),
+        recently_used: Vec::new(),
+    };
+    out.sort_branches(SortMode::Recent, false);
+    Ok(out)
+}
+
+/// The `user.email` git is configured with, i.e. whose commits count as yours.
+pub fn user_email(repo: &git2::Repository) -> Option<String> {
+    repo.config().ok()?.get_string("user.email").ok()
+}
+
+/// Patterns are matched against the name both with and without any remote prefix,
+/// so `dependabot/*` also leaves out `origin/dependabot/...`.
+fn is_excluded(branch: &git2::Branch, remotes: &[String], exclude: &[glob::Pattern]) -> bool {
+    let Ok(Some(name)) = branch.name() else {
+        return false;
+    };
+    let unprefixed = remotes
+        .iter()
+        .find_map(|r| name.strip_prefix(r.as_str())?.strip_prefix('/'));
+    exclude
+        .iter()
+        .any(|pattern| pattern.matches(name) || unprefixed.is_some_and(|n| pattern.matches(n)))
+}
+
+/// Branch ages are measured against the first special branch this repo has.
+fn age_base(repo: &git2::Repository, special_branches: &[String]) -> Option<git2::Oid> {
+    special_branches.iter().find_map(|name| {
+        repo.find_branch(name, BranchType::Local)
+            .ok()?
+            .get()
+            .peel_to_commit()
+            .ok()
+            .map(|c| c.id())
+    })
+}
+
+fn read_branch(
+    repo: &git2::Repository,
+    remotes: &[String],
+    branch: &git2::Branch,
+    branch_type: BranchType,
+) -> Result<Branch> {
+    let remote = match branch_type {
+        BranchType::Local => None,
+        BranchType::Remote => {
+            let refname = branch.get().name().unwrap_or_default();
+            remotes
+                .iter()
+                .find(|r| refname.starts_with(&format!("refs/remotes/{r}/")))
+                .cloned()
+        }
+    };
+    let full_name = branch.name()?.unwrap().to_owned();
+    let upstream_branch = branch.upstream().ok();
+    let ahead_behind = upstream_branch
+        .as_ref()
+        .and_then(|upstream| ahead_behind(repo, branch, upstream));
+    let upstream =
+        upstream_branch.and_then(|upstream| upstream.name().ok().flatten().map(ToOwned::to_owned));
+    let description = match branch_type {
+        BranchType::Local => description(repo, &full_name),
+        BranchType::Remote => None,
+    };
+    let is_current = branch.is_head();
+    let git_ref = branch.get();
+    let (tip, broken) = match git_ref.peel_to_commit() {
+        Ok(c) => (Some((c.id(), c.time())), None),
+        Err(_) => (None, Some(why_not_a_commit(repo, git_ref))),
+    };
+    Ok(Branch {
+        name: full_name.clone(),
+        full_name,
+        kind: match branch_type {
+            BranchType::Local => RefKind::Local,
+            BranchType::Remote => RefKind::Remote,
+        },
+        remote,
+        upstream,
+        description,
+        tip,
+        broken,
+        loaded: false,
+        last_commit: None,
+        age: None,
+        unique_commits: None,
+        ahead_behind,
+        is_current,
+        merged: false,
+        worktree: None,
+        pull_request: None,
+    })
+}
+
+/// `branch.<name>.description` from the repo's config, if there's anything in it.
+fn description(repo: &git2::Repository, branch: &str) -> Option<String> {
+    let description = repo
+        .config()
+        .ok()?
+        .get_string(&format!("branch.{branch}.description"))
+        .ok()?;
+    let description = description.trim();
+    (!description.is_empty()).then(|| description.to_owned())
+}
+
+/// Briefly, what a ref that couldn't be peeled to a commit points at instead.
+fn why_not_a_commit(repo: &git2::Repository, reference: &git2::Reference) -> String {
+    let Some(target) = reference.resolve().ok().and_then(|r| r.target()) else {
+        return "and it can't be resolved".to_owned();
+    };
+    match repo.find_object(target, None) {
+        Ok(object) => match object.kind() {
+            Some(kind) => format!("it points at a {kind}"),
+            None => "it points at something unknown".to_owned(),
+        },
+        Err(_) => format!(
+            "it points at {}, which is missing",
+            &target.to_string()[..7]
+        ),
+    }
+}
+
+/// The repo's tags, as rows for the table. Ones that don't point at a commit are left out.
+fn read_tags(repo: &git2::Repository) -> Result<Vec<Branch>> {
+    let mut tags = Vec::new();
+    for name in repo.tag_names(None)?.iter().flatten() {
+        // Annotated tags point at a tag object, which in turn points at the commit.
+        let commit = repo
+            .find_reference(&format!("refs/tags/{name}"))
+            .and_then(|r| r.peel_to_commit());
+        let Ok(commit) = commit else {
+            continue;
+        };
+        tags.push(Branch {
+            name: name.to_owned(),
+            full_name: name.to_owned(),
+            kind: RefKind::Tag,
+            remote: None,
+            upstream: None,
+            description: None,
+            tip: Some((commit.id(), commit.time())),
+            broken: None,
+            loaded: false,
+            last_commit: None,
+            age: None,
+            unique_commits: None,
+            ahead_behind: None,
+            is_current: false,
+            merged: false,
+            worktree: None,
+            pull_request: None,
+        });
+    }
+    Ok(tags)
+}
+
+/// Which branches are checked out in other worktrees of this repo, and where.
+fn other_worktrees(
+    repo: &git2::Repository,
+) -> std::collections::HashMap<String, std::path::PathBuf> {
+    let mut checkouts = std::collections::HashMap::new();
+    let mut add = |worktree_repo: &git2::Repository| {
+        let (Some(path), Ok(head)) = (worktree_repo.workdir(), worktree_repo.head()) else {
+            return;
+        };
+        if head.is_branch() && Some(path) != repo.workdir() {
+            if let Some(name) = head.shorthand() {
+                // Without the trailing slash git2 gives workdirs.
+                checkouts.insert(name.to_owned(), path.components().collect());
+            }
+        }
+    };
+    // From a linked worktree, the main worktree counts as another one too.
+    if repo.is_worktree() {
+        if let Ok(main) = git2::Repository::open(repo.commondir()) {
+            add(&main);
+        }
+    }
+    let names = repo.worktrees().ok();
+    for name in names.iter().flat_map(|names| names.iter().flatten()) {
+        let worktree = repo.find_worktree(name);
+        if let Ok(worktree_repo) = worktree.and_then(|w| git2::Repository::open_from_worktree(&w)) {
+            add(&worktree_repo);
+        }
+    }
+    checkouts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::date;
+
+    #[test]
+    fn out_of_range_commit_times_are_blank() {
+        let time = CommitTime::new_or_blank(git2::Time::new(i64::MAX, 0), DEFAULT_DATE_FORMAT);
+        assert_eq!(time.show(DateStyle::Relative), "");
+        assert_eq!(time.show(DateStyle::Absolute), "");
+    }
+
+    #[test]
+    fn relative_time_in_the_past() {
+        let now = date(2024, 6, 1).at(12, 0, 0, 0);
+        let then = date(2024, 5, 29).at(10, 0, 0, 0);
+        assert_eq!(relative_time(then, now).unwrap(), "3 days, 2 hours ago");
+    }
+
+    #[test]
+    fn relative_time_in_the_future() {
+        let now = date(2024, 6, 1).at(12, 0, 0, 0);
+        let then = date(2024, 6, 1).at(12, 5, 0, 0);
+        assert_eq!(relative_time(then, now).unwrap(), "in 5 minutes");
+    }
+
+    #[test]
+    fn relative_time_just_now() {
+        let now = date(2024, 6, 1).at(12, 0, 20, 0);
+        let then = date(2024, 6, 1).at(12, 0, 0, 0);
+        assert_eq!(relative_time(then, now).unwrap(), "just now");
+    }
+
+    #[test]
+    fn time_since_a_commit_made_ahead_of_utc() {
+        let now = jiff::Timestamp::from_second(1_717_243_200).unwrap();
+        // Made an hour earlier, by someone at +10:00.
+        let committed = git2::Time::new(now.as_second() - 3600, 10 * 60);
+        assert_eq!(
+            human_friendly_time_since(committed, now).unwrap(),
+            "1 hour ago"
+        );
+    }
+
+    #[test]
+    fn time_since_a_commit_made_across_a_dst_change() {
+        // US clocks went forward at 2am on 2024-03-10, so 1:30am EST to 3:30am EDT
+        // is only an hour later, even though the wall clock moved two.
+        let committed_at = "2024-03-10T01:30:00-05:00"
+            .parse::<jiff::Timestamp>()
+            .unwrap();
+        let now = "2024-03-10T03:30:00-04:00"
+            .parse::<jiff::Timestamp>()
+            .unwrap();
+        let committed = git2::Time::new(committed_at.as_second(), -5 * 60);
+        assert_eq!(
+            human_friendly_time_since(committed, now).unwrap(),
+            "1 hour ago"
+        );
+    }
+
+    /// A new, empty repo in the temp dir, named for the test using it.
+    fn scratch_repo(test: &str) -> git2::Repository {
+        let dir =
+            std::env::temp_dir().join(format!("git-branch-picker-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        git2::Repository::init(&dir).unwrap()
+    }
+
+    /// Commits `contents` to `file` on the current branch.
+    fn commit_file(git: &git2::Repository, file: &str, contents: &str, msg: &str) -> git2::Oid {
+        let workdir = git.workdir().unwrap();
+        std::fs::write(workdir.join(file), contents).unwrap();
+        let mut index = git.index().unwrap();
+        index.add_path(std::path::Path::new(file)).unwrap();
+        index.write().unwrap();
+        let tree = git.find_tree(index.write_tree().unwrap()).unwrap();
+        let me = git2::Signature::now("Jo", "jo@example.com").unwrap();
+        let parent = head_commit(git).map(|id| git.find_commit(id).unwrap());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        git.commit(Some("HEAD"), &me, &me, msg, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn a_branch_with_the_same_name_as_a_tag_is_still_the_branch() {
+        let git = scratch_repo("branch-and-tag");
+        let on_branch = commit_file(&git, "a", "1", "on the branch");
+        git.branch("foo", &git.find_commit(on_branch).unwrap(), false)
+            .unwrap();
+        let tagged = commit_file(&git, "a", "2", "tagged");
+        git.tag_lightweight("foo", git.find_commit(tagged).unwrap().as_object(), false)
+            .unwrap();
+
+        let repo = read_branches(&git).unwrap();
+        repo.checkout_branch("foo").unwrap();
+        assert_eq!(git.head().unwrap().name(), Some("refs/heads/foo"));
+        assert_eq!(head_commit(&git), Some(on_branch));
+        let commits = repo.recent_commits("refs/heads/foo", 1).unwrap();
+        assert_eq!(commits[0].msg, "on the branch");
+
+        repo.checkout_tag("foo").unwrap();
+        assert!(git.head_detached().unwrap());
+        assert_eq!(head_commit(&git), Some(tagged));
+        let _ = std::fs::remove_dir_all(git.workdir().unwrap());
+    }
+
+    #[test]
+    fn checking_out_over_local_changes_touches_nothing() {
+        let git = scratch_repo("checkout-conflict");
+        let first = commit_file(&git, "a", "1", "first");
+        git.branch("old", &git.find_commit(first).unwrap(), false)
+            .unwrap();
+        let second = commit_file(&git, "a", "2", "second");
+        std::fs::write(git.workdir().unwrap().join("a"), "uncommitted").unwrap();
+
+        let repo = read_branches(&git).unwrap();
+        match repo.checkout_branch("old") {
+            Err(CheckoutError::Conflicts { paths, .. }) => assert_eq!(paths, ["a"]),
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+        assert_eq!(head_commit(&git), Some(second));
+        let contents = std::fs::read_to_string(git.workdir().unwrap().join("a")).unwrap();
+        assert_eq!(contents, "uncommitted");
+        let _ = std::fs::remove_dir_all(git.workdir().unwrap());
+    }
+
+    fn branch_at(name: &str, seconds: i64, ahead: usize) -> Branch {
+        Branch {
+            name: name.to_owned(),
+            full_name: name.to_owned(),
+            kind: RefKind::Local,
+            remote: None,
+            upstream: None,
+            description: None,
+            tip: Some((git2::Oid::zero(), git2::Time::new(seconds, 0))),
+            broken: None,
+            loaded: true,
+            last_commit: None,
+            age: None,
+            unique_commits: None,
+            ahead_behind: Some((ahead, 0)),
+            is_current: false,
+            merged: false,
+            worktree: None,
+            pull_request: None,
+        }
+    }
+
+    fn names(branches: &[Branch]) -> Vec<&str> {
+        branches.iter().map(|b| b.full_name.as_str()).collect()
+    }
+
+    #[test]
+    fn unused_branches_stay_in_recent_order_after_used_ones() {
+        let mut branches = [
+            branch_at("b", 100, 0),
+            branch_at("used", 50, 0),
+            branch_at("a", 100, 0),
+        ];
+        SortMode::LastUsed.sort(&mut branches, &["used".to_owned()]);
+        assert_eq!(names(&branches), ["used", "a", "b"]);
+    }
+
+    #[test]
+    fn branches_made_in_the_same_second_sort_by_name() {
+        let mut branches = [
+            branch_at("c", 100, 0),
+            branch_at("a", 100, 0),
+            branch_at("newest", 200, 0),
+            branch_at("b", 100, 0),
+        ];
+        SortMode::Recent.sort(&mut branches, &[]);
+        assert_eq!(names(&branches), ["newest", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn branches_equally_far_ahead_sort_by_name() {
+        let mut branches = [
+            branch_at("b", 100, 2),
+            branch_at("most", 100, 5),
+            branch_at("a", 200, 2),
+        ];
+        SortMode::MostAhead.sort(&mut branches, &[]);
+        assert_eq!(names(&branches), ["most", "a", "b"]);
+    }
+}
@@ -1,314 +1,3377 @@
-use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use anyhow::{Context, Result};
+use clap::Parser;
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+    MouseEventKind,
+};
 use git2::BranchType;
+use git_branch_picker::{
+    Branch, CheckoutError, DateStyle, Details, PreviewCommit, PullRequest, RefKind, Repo, SortMode,
+};
 use ratatui::{
     layout::{Constraint, Layout, Margin, Rect},
     style::{palette::tailwind, Color, Modifier, Style, Stylize},
-    text::Text,
+    text::{Line, Span, Text},
     widgets::{
-        Block, BorderType, Cell, HighlightSpacing, Paragraph, Row, Scrollbar, ScrollbarOrientation,
-        ScrollbarState, Table, TableState,
+        Block, BorderType, Cell, Clear, HighlightSpacing, Paragraph, Row, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Table, TableState, Wrap,
     },
-    DefaultTerminal, Frame,
+    Frame,
 };
+use std::io::Write;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 const ITEM_HEIGHT: usize = 1;
-const PALETTES: [tailwind::Palette; 4] = [
-    tailwind::BLUE,
-    tailwind::EMERALD,
-    tailwind::INDIGO,
-    tailwind::RED,
+/// Goes in front of the name of the branch that's checked out.
+const CURRENT_BRANCH_MARKER: &str = "● ";
+/// Goes in front of branches checked out in other worktrees, like `git branch` does.
+const WORKTREE_MARKER: &str = "+ ";
+/// Goes in front of branches marked for bulk deletion.
+const MARKED_MARKER: &str = "✓ ";
+/// Every palette the config's `palettes` can name.
+static NAMED_PALETTES: [(&str, tailwind::Palette); 22] = [
+    ("slate", tailwind::SLATE),
+    ("gray", tailwind::GRAY),
+    ("zinc", tailwind::ZINC),
+    ("neutral", tailwind::NEUTRAL),
+    ("stone", tailwind::STONE),
+    ("red", tailwind::RED),
+    ("orange", tailwind::ORANGE),
+    ("amber", tailwind::AMBER),
+    ("yellow", tailwind::YELLOW),
+    ("lime", tailwind::LIME),
+    ("green", tailwind::GREEN),
+    ("emerald", tailwind::EMERALD),
+    ("teal", tailwind::TEAL),
+    ("cyan", tailwind::CYAN),
+    ("sky", tailwind::SKY),
+    ("blue", tailwind::BLUE),
+    ("indigo", tailwind::INDIGO),
+    ("violet", tailwind::VIOLET),
+    ("purple", tailwind::PURPLE),
+    ("fuchsia", tailwind::FUCHSIA),
+    ("pink", tailwind::PINK),
+    ("rose", tailwind::ROSE),
 ];
+/// What `C` cycles through, unless the config says otherwise.
+const DEFAULT_PALETTES: [&str; 4] = ["blue", "emerald", "indigo", "red"];
+
+/// User settings, read from `~/.config/git-branch-picker/config.toml`.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// Branch names often have prefixes, which can be shortened for this picker,
+    /// e.g. `"achalmers/" = "ac/"`. Applied in the order they're written,
+    /// so an earlier one can change what a later one matches.
+    replacements: indexmap::IndexMap<String, String>,
+    /// Which of `palettes` to use. Remembered when it's changed in the picker.
+    palette: Option<usize>,
+    /// The palettes `C` cycles through, e.g. `["teal", "rose"]`. Defaults to `DEFAULT_PALETTES`.
+    palettes: Vec<PaletteName>,
+    /// Replacement keys for actions in the branch list, e.g. `quit = ["ctrl-q"]`.
+    keys: std::collections::BTreeMap<Action, Vec<KeyBinding>>,
+    /// What counts as main/master etc., e.g. `["develop", "production"]`, if not those two.
+    /// The repo's default branch always does too.
+    special_branches: Option<Vec<String>>,
+    /// Keep main/master etc. at the top of the list, whatever it's sorted by.
+    pin_special_branches: bool,
+    /// Ask "are you sure?" before switching to main/master etc.
+    confirm_special_checkout: bool,
+    /// Wider commit subjects (in columns) are cut short, so that one can't squeeze out the other columns.
+    max_message_width: Option<usize>,
+    /// Wider branch names (in columns) lose their middle in the table, so one can't squeeze out the other columns.
+    max_name_width: Option<usize>,
+    /// How the date column writes times when it's showing them as dates (`t`), e.g. `"%a %b %d"`.
+    date_format: Option<String>,
+    /// If `date_format` couldn't be used, why not. It's ignored rather than refusing to start.
+    #[serde(skip)]
+    date_format_error: Option<String>,
+    /// What Enter (or whatever `checkout` is bound to) does with the selected branch.
+    on_enter: OnEnter,
+    /// Moving past the last branch goes back to the first, and vice versa. Defaults to true.
+    wrap: Option<bool>,
+    /// Which branch is selected when the picker opens.
+    initial_selection: InitialSelection,
+    /// Color the dates by how long ago they were, so stale branches stand out.
+    fade_old_dates: bool,
+    /// When the branches don't fill the screen, draw them in the middle of it rather than at the top.
+    center_table: bool,
+    /// Don't run the repo's post-checkout hook after switching, which `git checkout` would.
+    skip_hooks: bool,
+}
 
-/// Will be styled differently in the branch picker.
-const SPECIAL_BRANCHES: [&str; 2] = ["main", "master"];
+impl Config {
+    /// `$XDG_CONFIG_HOME/git-branch-picker`, or under `~/.config` if that's unset.
+    fn dir() -> Option<std::path::PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(std::path::PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".config"))
+            })?;
+        Some(config_dir.join("git-branch-picker"))
+    }
 
-/// Branch names often have prefixes,
-/// which can be shortened for this picker.
-const BRANCH_NAME_REPLACEMENTS: [(&str, &str); 2] = [("achalmers/", "ac/"), ("release/", "rel/")];
+    fn path() -> Option<std::path::PathBuf> {
+        Some(Self::dir()?.join("config.toml"))
+    }
 
-fn main() -> Result<()> {
-    let branches = read_branches()?;
-    let mut terminal = ratatui::init();
-    let mut app = App::new(branches)?;
-    app.run(&mut terminal)?;
-    ratatui::restore();
-    if app.user_switched_branch {
-        let Some(i) = app.state.selected() else {
-            return Ok(());
+    /// A missing config file just means the defaults, but a broken one is an error.
+    fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
         };
-        let mut branch_name = app.repo.branches[i].name.to_owned();
-        for (repl_from, repl_to) in BRANCH_NAME_REPLACEMENTS {
-            branch_name = branch_name.replace(repl_to, repl_from);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(
+                    anyhow::Error::new(e).context(format!("couldn't read {}", path.display()))
+                )
+            }
+        };
+        let mut config: Self =
+            toml::from_str(&text).with_context(|| format!("couldn't parse {}", path.display()))?;
+        if let Some(format) = &config.date_format {
+            if let Err(e) = jiff::fmt::strtime::format(format, &jiff::Zoned::now()) {
+                config.date_format_error = Some(format!("Ignoring date_format {format:?}: {e}"));
+                config.date_format = None;
+            }
+        }
+        Ok(config)
+    }
+
+    fn date_format(&self) -> &str {
+        self.date_format
+            .as_deref()
+            .unwrap_or(git_branch_picker::DEFAULT_DATE_FORMAT)
+    }
+
+    fn max_message_width(&self) -> usize {
+        self.max_message_width.unwrap_or(72)
+    }
+
+    fn max_name_width(&self) -> usize {
+        // Any less than this, and there'd be hardly anything of the name left either side.
+        self.max_name_width.unwrap_or(50).max(5)
+    }
+
+    fn palettes(&self) -> Vec<PaletteName> {
+        if self.palettes.is_empty() {
+            DEFAULT_PALETTES
+                .iter()
+                .map(|name| name.parse().unwrap())
+                .collect()
+        } else {
+            self.palettes.clone()
         }
-        let status = std::process::Command::new("git")
-            .args(["checkout", &branch_name])
-            .spawn()?
-            .wait()?;
-        if !status.success() {
-            anyhow::bail!("git checkout failed, status was {status}");
+    }
+
+    fn palette_index(&self) -> usize {
+        // Emerald, out of the defaults, or else whichever palette was listed first.
+        let default = if self.palettes.is_empty() { 1 } else { 0 };
+        self.palette.unwrap_or(default) % self.palettes().len()
+    }
+
+    /// Saves the palette choice, leaving the rest of the file (including comments) as it was.
+    fn save_palette(index: usize) -> Result<()> {
+        let path = Self::path().context("couldn't find a config directory")?;
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut doc: toml_edit::DocumentMut = text.parse()?;
+        doc["palette"] = toml_edit::value(index as i64);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
         }
+        std::fs::write(&path, doc.to_string())
+            .with_context(|| format!("couldn't write {}", path.display()))
     }
-    Ok(())
-}
 
-#[derive(Debug)]
-struct Branch {
-    name: String,
-    last_commit: Option<Commit>,
+    /// How a branch name is shown in the picker, after the configured replacements
+    /// (one after another, in file order).
+    fn abbreviate(&self, name: &str) -> String {
+        let mut name = name.to_owned();
+        for (repl_from, repl_to) in &self.replacements {
+            name = name.replace(repl_from, repl_to);
+        }
+        name
+    }
+
+    /// `abbreviate` for any branch. A remote-tracking branch's remote prefix is shortened
+    /// separately from the rest, so e.g. `"origin/" = "o/"` and `"achalmers/" = "ac/"` turn
+    /// `origin/achalmers/foo` into `o/ac/foo`, the same as the local `achalmers/foo` is shown.
+    fn abbreviate_branch(&self, branch: &Branch) -> String {
+        let rest = branch.remote.as_ref().and_then(|remote| {
+            let rest = branch
+                .full_name
+                .strip_prefix(remote.as_str())?
+                .strip_prefix('/')?;
+            Some((remote, rest))
+        });
+        match rest {
+            Some((remote, rest)) => self.abbreviate(&format!("{remote}/")) + &self.abbreviate(rest),
+            None => self.abbreviate(&branch.full_name),
+        }
+    }
 }
 
-#[derive(Debug)]
-struct Commit {
-    msg: String,
-    time: String,
+/// One of `NAMED_PALETTES`, by its index there.
+#[derive(Debug, Clone, Copy)]
+struct PaletteName(usize);
+
+impl PaletteName {
+    fn palette(self) -> &'static tailwind::Palette {
+        &NAMED_PALETTES[self.0].1
+    }
 }
 
-impl Branch {
-    fn ref_array(&self) -> [String; 3] {
-        let msg = self
-            .last_commit
-            .as_ref()
-            .map(|c| c.msg.clone())
-            .unwrap_or_default();
-        let time = self
-            .last_commit
-            .as_ref()
-            .map(|c| c.time.clone())
-            .unwrap_or_default();
-        [self.name.clone(), msg, time]
+impl std::str::FromStr for PaletteName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NAMED_PALETTES
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(Self)
+            .ok_or_else(|| {
+                let names: Vec<&str> = NAMED_PALETTES.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "unknown palette '{s}', expected one of {}",
+                    names.join(", ")
+                )
+            })
     }
 }
 
-#[derive(Debug)]
-struct Repo {
-    branches: Vec<Branch>,
-    root: String,
-}
-
-const TIME_PRINTER: jiff::fmt::friendly::SpanPrinter = jiff::fmt::friendly::SpanPrinter::new()
-    .spacing(jiff::fmt::friendly::Spacing::BetweenUnitsAndDesignators)
-    .comma_after_designator(true)
-    .designator(jiff::fmt::friendly::Designator::Verbose);
-
-fn human_friendly_time_since(t: git2::Time) -> Result<String> {
-    let committed_at =
-        jiff::Timestamp::from_second(t.seconds() + ((t.offset_minutes() as i64) * 60))?;
-    let committed_at = committed_at.in_tz("UTC")?.datetime();
-    let now = jiff::Zoned::now().datetime();
-    let since_commit = (now - committed_at).round(
-        jiff::SpanRound::new()
-            .smallest(jiff::Unit::Minute)
-            .days_are_24_hours(),
-    )?;
+impl<'de> serde::Deserialize<'de> for PaletteName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
 
-    Ok(TIME_PRINTER.span_to_string(&-since_commit))
+/// The choices for `on_enter` in the config file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OnEnter {
+    #[default]
+    Checkout,
+    /// Like `--print`.
+    Print,
+    /// Like `o`.
+    Detached,
+    /// Like `v`, toggling the preview pane instead of leaving the picker.
+    Preview,
 }
 
-fn read_branches() -> anyhow::Result<Repo> {
-    let repo = git2::Repository::open_from_env()?;
-    let branches = repo.branches(None)?;
-    let mut out_branches = Vec::new();
-    for branch in branches {
-        let (branch, branch_type) = branch?;
-        if branch_type == BranchType::Remote {
-            continue;
+impl OnEnter {
+    /// For the help screen, in place of `Action::Checkout`'s own description.
+    fn description(self) -> &'static str {
+        match self {
+            Self::Checkout => Action::Checkout.description(),
+            Self::Print => "print the selected branch's name and exit",
+            Self::Detached => Action::CheckoutDetached.description(),
+            Self::Preview => Action::TogglePreview.description(),
         }
-        let mut name = branch.name()?.unwrap().to_owned();
-        for (repl_from, repl_to) in BRANCH_NAME_REPLACEMENTS {
-            name = name.replace(repl_from, repl_to);
+    }
+}
+
+/// The choices for `initial_selection` in the config file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum InitialSelection {
+    /// The branch HEAD points at.
+    #[default]
+    Current,
+    /// Whatever's listed first.
+    Top,
+    /// The branch last switched to with the picker before the current one, like `git checkout -`.
+    /// The current branch if there isn't one.
+    Recent,
+}
+
+/// The branches most recently switched to with the picker, most recent first, for each repo.
+/// Kept in `history.toml` next to the config file, which stays hand-written.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct History {
+    /// Keyed by the path to the repo's (main) .git directory, so worktrees share a history.
+    repos: std::collections::BTreeMap<String, Vec<String>>,
+}
+
+impl History {
+    /// How many branches are remembered per repo.
+    const LENGTH: usize = 10;
+
+    fn path() -> Option<std::path::PathBuf> {
+        Some(Config::dir()?.join("history.toml"))
+    }
+
+    /// The history is only a convenience, so if it can't be read it's treated as empty.
+    fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|text| toml::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn key(repo: &git2::Repository) -> String {
+        let dir = repo.commondir();
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_owned());
+        dir.display().to_string()
+    }
+
+    fn recently_used(repo: &git2::Repository) -> Vec<String> {
+        Self::load()
+            .repos
+            .remove(&Self::key(repo))
+            .unwrap_or_default()
+    }
+
+    /// Moves this branch to the front of the repo's history.
+    fn record(repo: &git2::Repository, branch: &str) -> Result<()> {
+        let path = Self::path().context("couldn't find a config directory")?;
+        let mut history = Self::load();
+        let recent = history.repos.entry(Self::key(repo)).or_default();
+        recent.retain(|name| name != branch);
+        recent.insert(0, branch.to_owned());
+        recent.truncate(Self::LENGTH);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
         }
-        let git_ref = branch.get();
-        let git_commit = git_ref.peel_to_commit().ok();
-        let last_commit = git_commit.map(|c| {
-            let human_friendly = human_friendly_time_since(c.time()).unwrap();
-            let msg = c.message().unwrap_or("<empty>").to_owned();
-            let msg = msg.lines().next().unwrap().to_owned();
-            (
-                Commit {
-                    time: human_friendly,
-                    msg,
-                },
-                c.time(),
-            )
-        });
-        out_branches.push((
-            last_commit.as_ref().map(|lc| lc.1),
-            Branch {
-                name,
-                last_commit: last_commit.map(|lc| lc.0),
-            },
-        ));
+        std::fs::write(&path, toml::to_string(&history)?)
+            .with_context(|| format!("couldn't write {}", path.display()))
     }
-    out_branches.sort_by(|x, y| x.0.cmp(&y.0).reverse());
-    let out_branches = out_branches.into_iter().map(|x| x.1).collect();
+}
 
-    let root = repo.path().parent().unwrap().display().to_string();
-    let home = std::env::var("HOME");
-    let root = if let Ok(home) = home {
-        if let Some(relative_to_homedir) = root.strip_prefix(&home) {
-            format!("~{relative_to_homedir}")
-        } else {
-            root
+/// `--sort`'s choices, one for each `SortMode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum SortArg {
+    /// Most recently committed to first.
+    Recent,
+    /// Alphabetically.
+    Name,
+    /// Furthest ahead of upstream first.
+    Ahead,
+    /// Most recently switched to with the picker first.
+    LastUsed,
+}
+
+impl From<SortArg> for SortMode {
+    fn from(sort: SortArg) -> Self {
+        match sort {
+            SortArg::Recent => Self::Recent,
+            SortArg::Name => Self::Alphabetical,
+            SortArg::Ahead => Self::MostAhead,
+            SortArg::LastUsed => Self::LastUsed,
         }
-    } else {
-        root
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ListFormat {
+    /// The same columns as the picker, lined up.
+    Table,
+    /// One object per branch, in a JSON array.
+    Json,
+}
+
+/// A branch as `--list --format json` prints it.
+#[derive(Debug, serde::Serialize)]
+struct ListedBranch<'a> {
+    name: &'a str,
+    /// Which remote it's from, for remote-tracking branches.
+    remote: Option<&'a str>,
+    current: bool,
+    /// Already reachable from HEAD.
+    merged: bool,
+    /// From `git branch --edit-description`.
+    description: Option<&'a str>,
+    hash: Option<String>,
+    author: Option<&'a str>,
+    message: Option<&'a str>,
+    /// When the last commit was made, in RFC 3339 format with the committer's offset.
+    timestamp: Option<String>,
+    ahead: Option<usize>,
+    behind: Option<usize>,
+    /// Commits not on main/master, see `Branch::unique_commits`.
+    commits: Option<usize>,
+    /// Only looked up with `--github`.
+    pull_request: Option<&'a PullRequest>,
+}
+
+/// `--list`: prints the branches the picker would show to begin with, without any TUI.
+fn print_list(git: &git2::Repository, mut repo: Repo, config: &Config, args: &Args) -> Result<()> {
+    repo.branches.retain(|b| match b.kind {
+        RefKind::Local => true,
+        RefKind::Remote => args.remote.is_some() && b.remote == args.remote,
+        RefKind::Tag => false,
+    });
+    repo.sort_branches(args.sort.into(), config.pin_special_branches);
+    let max_msg_width = match args.format {
+        ListFormat::Table => config.max_message_width(),
+        // Scripts can cut it short themselves if they want.
+        ListFormat::Json => usize::MAX,
     };
-    Ok(Repo {
-        branches: out_branches,
-        root,
-    })
+    for branch in &mut repo.branches {
+        branch.load_details(
+            git,
+            repo.age_base,
+            repo.head,
+            max_msg_width,
+            config.date_format(),
+        );
+        // Scripts want the real names.
+        branch.name = branch.full_name.clone();
+    }
+    if args.columns().contains(&Column::PullRequest) {
+        if let Some(pull_requests) = git_branch_picker::github_pull_requests(gh_dir(git)) {
+            repo.set_pull_requests(&pull_requests);
+        }
+    }
+    match write_list(&mut std::io::stdout().lock(), &repo, args) {
+        // e.g. piped into `head`, which has all it wants.
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
 }
 
-#[derive(Debug)]
-struct App {
-    repo: Repo,
-    exit: bool,
-    state: TableState,
-    scroll_state: ScrollbarState,
-    colors: TableColors,
-    longest_item_lens: ConstraintSizes,
-    color_index: usize,
-    /// If true, run the git checkout command when the TUI exits.
-    user_switched_branch: bool,
+fn write_list(out: &mut impl Write, repo: &Repo, args: &Args) -> std::io::Result<()> {
+    match args.format {
+        ListFormat::Table => {
+            let columns = args.columns();
+            let sizes =
+                ConstraintSizes::calculate(repo.branches.iter(), DateStyle::Relative, usize::MAX);
+            let mut write_row = |cells: Vec<String>| {
+                let mut line = String::new();
+                for (column, cell) in columns.iter().zip(cells) {
+                    let width = column.width(sizes, DateMode::LastCommit) as usize;
+                    // Padded by hand, since `{:width$}` counts chars rather than columns.
+                    let padding = width.saturating_sub(cell.width());
+                    // The same gap between columns as the picker's table.
+                    line.push_str(&format!("{cell}{:padding$} ", ""));
+                }
+                writeln!(out, "{}", line.trim_end())
+            };
+            let header = columns.iter().map(|c| c.header(DateMode::LastCommit));
+            let marker_width = CURRENT_BRANCH_MARKER.chars().count();
+            write_row(
+                header
+                    .map(|h| match h {
+                        "Name" => format!("{:marker_width$}{h}", ""),
+                        _ => h.to_owned(),
+                    })
+                    .collect(),
+            )?;
+            for branch in &repo.branches {
+                let cells = columns.iter().map(|&column| {
+                    let cell = column.cell(branch, DateMode::LastCommit, DateStyle::Relative);
+                    match column {
+                        Column::Name if branch.is_current => {
+                            format!("{CURRENT_BRANCH_MARKER}{cell}")
+                        }
+                        Column::Name => format!("{:marker_width$}{cell}", ""),
+                        _ => cell,
+                    }
+                });
+                write_row(cells.collect())?;
+            }
+        }
+        ListFormat::Json => {
+            let listed: Vec<ListedBranch> = repo
+                .branches
+                .iter()
+                .map(|b| {
+                    let commit = b.last_commit.as_ref();
+                    ListedBranch {
+                        name: &b.full_name,
+                        remote: b.remote.as_deref(),
+                        current: b.is_current,
+                        merged: b.merged,
+                        description: b.description.as_deref(),
+                        hash: b.tip.map(|(oid, _)| oid.to_string()),
+                        author: commit.map(|c| c.author.as_str()),
+                        message: commit.map(|c| c.msg.as_str()),
+                        timestamp: b.tip.and_then(|(_, time)| rfc3339(time)),
+                        ahead: b.ahead_behind.map(|(ahead, _)| ahead),
+                        behind: b.ahead_behind.map(|(_, behind)| behind),
+                        commits: b.unique_commits,
+                        pull_request: b.pull_request.as_ref(),
+                    }
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &listed)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// e.g. "2024-06-01T14:32:00+02:00", in the timezone the commit was made in.
+fn rfc3339(time: git2::Time) -> Option<String> {
+    let timestamp = jiff::Timestamp::from_second(time.seconds()).ok()?;
+    let offset = jiff::tz::Offset::from_seconds(time.offset_minutes() * 60).ok()?;
+    Some(timestamp.display_with_offset(offset).to_string())
 }
 
+/// Exit codes, so that scripts can tell a cancelled pick from a failed one.
+/// 0 means a branch was picked, or the user quit without picking one.
+const EXIT_FAILED: u8 = 1;
+/// Launched somewhere that isn't inside a git repo.
+const EXIT_NOT_A_REPO: u8 = 2;
+/// Ctrl-C'd, the same as a shell reports for a program killed by SIGINT.
+const EXIT_INTERRUPTED: u8 = 130;
+
+/// Why the picker couldn't do what it was asked to.
 #[derive(Debug)]
-struct TableColors {
-    buffer_bg: Color,
-    header_bg: Color,
-    header_fg: Color,
-    row_fg: Color,
-    selected_row_style_fg: Color,
-    selected_column_style_fg: Color,
-    selected_cell_style_fg: Color,
-    normal_row_color: Color,
-    unusual_row_color: Color,
-    footer_border_color: Color,
+enum Failure {
+    /// With the `--repo` path, if one was given.
+    NotARepo(Option<std::path::PathBuf>),
+    Checkout(CheckoutError),
+    /// Ctrl-C was pressed in the picker.
+    Interrupted,
+    Other(anyhow::Error),
 }
 
-impl TableColors {
-    const fn new(color: &tailwind::Palette) -> Self {
-        Self {
-            buffer_bg: tailwind::SLATE.c950,
-            header_bg: color.c900,
-            header_fg: tailwind::SLATE.c200,
-            row_fg: tailwind::SLATE.c200,
-            selected_row_style_fg: color.c400,
-            selected_column_style_fg: color.c400,
-            selected_cell_style_fg: color.c600,
-            normal_row_color: tailwind::SLATE.c950,
-            unusual_row_color: tailwind::SLATE.c800,
-            footer_border_color: color.c400,
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::NotARepo(_) => EXIT_NOT_A_REPO,
+            Self::Interrupted => EXIT_INTERRUPTED,
+            Self::Checkout(_) | Self::Other(_) => EXIT_FAILED,
         }
     }
 }
 
-impl App {
-    fn new(repo: Repo) -> Result<Self> {
-        Ok(Self {
-            exit: false,
-            state: TableState::default().with_selected(0),
-            scroll_state: ScrollbarState::new((repo.branches.len() - 1) * ITEM_HEIGHT),
-            colors: TableColors::new(&PALETTES[1]),
-            color_index: 1,
-            longest_item_lens: ConstraintSizes::calculate(&repo.branches),
-            repo,
-            user_switched_branch: false,
-        })
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotARepo(None) => f.write_str("not inside a git repository"),
+            Self::NotARepo(Some(path)) => write!(f, "{} isn't a git repository", path.display()),
+            Self::Checkout(e) => match e.hint() {
+                Some(hint) => write!(f, "{e}\nhint: {hint}"),
+                None => write!(f, "{e}"),
+            },
+            Self::Interrupted => f.write_str("interrupted"),
+            Self::Other(e) => write!(f, "{e:#}"),
+        }
     }
+}
 
-    fn set_colors(&mut self) {
-        self.colors = TableColors::new(&PALETTES[self.color_index]);
+impl From<CheckoutError> for Failure {
+    fn from(e: CheckoutError) -> Self {
+        Self::Checkout(e)
     }
+}
 
-    /// runs the application's main loop until the user quits
-    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
-        while !self.exit {
-            terminal.draw(|frame| self.draw(frame))?;
-            self.handle_events()?;
+impl From<anyhow::Error> for Failure {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Other(e)
+    }
+}
+
+impl From<git2::Error> for Failure {
+    fn from(e: git2::Error) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+/// A tiny TUI for picking a git branch and switching to it.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Don't let `git checkout` print anything after the picker exits, unless it fails.
+    #[arg(short, long)]
+    quiet: bool,
+    /// Print the picked branch's name to stdout instead of checking it out,
+    /// e.g. for `git checkout "$(git-branch-picker --print)"`.
+    #[arg(long)]
+    print: bool,
+    /// Stop at the first and last branch, instead of wrapping around to the other end.
+    /// Same as `wrap = false` in the config file.
+    #[arg(long)]
+    no_wrap: bool,
+    /// Show each branch tip's commit hash, after the name. Same as adding `hash` to `--columns`.
+    #[arg(long)]
+    show_hash: bool,
+    /// Show each branch's open GitHub pull request, if it has one. Needs the `gh` CLI,
+    /// logged in. Same as adding `pull-request` to `--columns`.
+    #[arg(long)]
+    github: bool,
+    /// On the way out, print the repo's top directory to stdout, for a shell function to `cd` to.
+    /// If the branch picked (or selected when quitting) is checked out in another worktree,
+    /// that worktree's printed instead, and nothing's switched.
+    #[arg(long, conflicts_with_all = ["print", "list"])]
+    cd: bool,
+    /// Leave out branches matching this glob, e.g. `dependabot/*`. Can be given more than once.
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<glob::Pattern>,
+    /// Only list branches whose tip commit you authored, going by git's `user.email`.
+    #[arg(long)]
+    mine: bool,
+    /// Only use the terminal's default colors. Also set by the `NO_COLOR` environment variable.
+    #[arg(long)]
+    no_color: bool,
+    /// After switching, fetch the branch's upstream and fast-forward to it, like
+    /// `git pull --ff-only`. Press `p` instead of Enter to do this for one switch.
+    #[arg(long)]
+    pull: bool,
+    /// Fetch from the remote as soon as the picker opens (or press `f`), without waiting for it.
+    #[arg(long)]
+    fetch: bool,
+    /// Also list remote-tracking branches from this remote (cycle remotes with `r`).
+    #[arg(long, value_name = "NAME")]
+    remote: Option<String>,
+    /// Use the repository at this path, instead of the one the current directory is in.
+    #[arg(long, value_name = "PATH")]
+    repo: Option<std::path::PathBuf>,
+    /// What order to list the branches in to begin with (press `s` to change it).
+    #[arg(long, value_enum, default_value = "recent")]
+    sort: SortArg,
+    /// Print the branches to stdout instead of opening the picker.
+    #[arg(long)]
+    list: bool,
+    /// How `--list` prints them.
+    #[arg(long, value_enum, default_value = "table", requires = "list")]
+    format: ListFormat,
+    /// Which columns to show, and in what order.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "name,ahead-behind,msg,author,date"
+    )]
+    columns: Vec<Column>,
+}
+
+impl Args {
+    /// `--columns`, plus the hash and pull request columns if `--show-hash` and `--github` asked for them.
+    fn columns(&self) -> Vec<Column> {
+        let mut columns = self.columns.clone();
+        if self.github && !columns.contains(&Column::PullRequest) {
+            columns.insert(1.min(columns.len()), Column::PullRequest);
         }
-        Ok(())
+        if self.show_hash && !columns.contains(&Column::Hash) {
+            columns.insert(1.min(columns.len()), Column::Hash);
+        }
+        columns
     }
 
-    fn draw(&mut self, frame: &mut Frame) {
-        let vertical = &Layout::vertical([Constraint::Min(5), Constraint::Length(4)]);
-        let rects = vertical.split(frame.area());
+    /// For `--mine`, whose branches to keep.
+    fn author(&self, git: &git2::Repository) -> Result<Option<String>> {
+        if !self.mine {
+            return Ok(None);
+        }
+        match git_branch_picker::user_email(git) {
+            Some(email) => Ok(Some(email)),
+            None => anyhow::bail!("--mine needs git's user.email to be set"),
+        }
+    }
+}
 
-        self.set_colors();
+fn main() -> std::process::ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("git-branch-picker: {failure}");
+            std::process::ExitCode::from(failure.exit_code())
+        }
+    }
+}
 
-        self.render_table(frame, rects[0]);
-        self.render_scrollbar(frame, rects[0]);
-        self.render_footer(frame, rects[1]);
+fn run(args: &Args) -> Result<(), Failure> {
+    // Check this before touching the terminal, so e.g. a shell alias run in the
+    // wrong directory just prints one clear line.
+    let opened = match &args.repo {
+        Some(path) => git2::Repository::open(path),
+        None => git2::Repository::open_from_env(),
+    };
+    let repo = match opened {
+        Ok(repo) => repo,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            return Err(Failure::NotARepo(args.repo.clone()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let config = Config::load()?;
+    let author = args.author(&repo)?;
+    if args.list {
+        let branches = read_branches(&repo, &config, &args.exclude, author.as_deref())?;
+        check_remote(&branches, args)?;
+        print_list(&repo, branches, &config, args)?;
+        return Ok(());
+    }
+    let mut terminal = init_terminal()?;
+    let picked = scan_branches(
+        &mut terminal,
+        &repo,
+        &config,
+        &args.exclude,
+        author.as_deref(),
+    )
+    .and_then(|branches| {
+        check_remote(&branches, args)?;
+        let mut app = App::new(branches, config, args, author)?;
+        app.run(&mut terminal)?;
+        Ok(app)
+    });
+    // Leave the alternate screen before any error from the picker (or anything the checkout
+    // says) gets printed. The terminal goes first, since dropping it can still write to stderr.
+    drop(terminal);
+    restore_terminal();
+    let app = picked?;
+    if app.interrupted {
+        return Err(Failure::Interrupted);
+    }
+    if app.color_index != app.config.palette_index() {
+        if let Err(e) = Config::save_palette(app.color_index) {
+            eprintln!("git-branch-picker: couldn't save the palette choice: {e:#}");
+        }
+    }
+    if let Some(path) = &app.worktree_to_print {
+        // It's the branch's directory, for `p` in the other worktree dialog or for `--cd`.
+        println!("{}", path.display());
+        return Ok(());
     }
+    switch_to_picked(&repo, &app, args)?;
+    if args.cd && !app.print_only {
+        let switched = app.new_branch.is_some() || app.user_switched_branch;
+        // Quitting still goes to the selected branch's directory, if it's in another worktree.
+        let elsewhere = app
+            .selected_branch()
+            .filter(|_| !switched)
+            .and_then(|b| b.worktree.as_deref());
+        if let Some(dir) = elsewhere.or(repo.workdir()) {
+            println!("{}", dir.display());
+        }
+    }
+    Ok(())
+}
 
-    fn handle_events(&mut self) -> Result<()> {
-        match event::read()? {
-            // it's important to check that the event is a key press event as
-            // crossterm also emits key release and repeat events on Windows.
-            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                self.handle_key_event(key_event)
-            }
-            _ => {}
-        };
-        Ok(())
+/// Does whatever was picked: makes the new branch, or switches to the selected one (or just
+/// prints its name), and lets the user know how it went.
+fn switch_to_picked(repo: &git2::Repository, app: &App, args: &Args) -> Result<(), Failure> {
+    let old_head = git_branch_picker::head_commit(repo);
+    if let Some(new_branch) = &app.new_branch {
+        app.repo.create_branch_at_head(new_branch)?;
+        app.repo.checkout_branch(new_branch)?;
+        post_checkout(repo, &app.config, old_head)?;
+        record_checkout(&app.repo, new_branch);
+        if !args.quiet {
+            eprintln!("Switched to a new branch '{new_branch}'");
+        }
+        return Ok(());
     }
-    fn handle_key_event(&mut self, key_event: KeyEvent) {
-        match key_event.code {
-            KeyCode::Char('q') | KeyCode::Esc => self.exit(),
-            KeyCode::Enter => {
-                self.switch_branch();
-                self.exit();
+    if app.user_switched_branch {
+        let Some(branch) = app.selected_branch() else {
+            return Ok(());
+        };
+        if app.print_only {
+            println!("{}", branch.full_name);
+            return Ok(());
+        }
+        if app.stash_before_checkout {
+            app.repo.stash_changes()?;
+            if !args.quiet {
+                eprintln!("Stashed your uncommitted changes");
+            }
+        }
+        if app.detach_requested {
+            let Some((tip, _)) = branch.tip else {
+                let error = anyhow::anyhow!("{} doesn't point at a commit", branch.full_name);
+                return Err(Failure::Other(error));
+            };
+            app.repo.checkout_detached(tip)?;
+            post_checkout(repo, &app.config, old_head)?;
+            if !args.quiet {
+                eprintln!(
+                    "HEAD is now at {} ({}), detached",
+                    &tip.to_string()[..7],
+                    branch.full_name
+                );
             }
-            KeyCode::Left | KeyCode::Up | KeyCode::Char('k') | KeyCode::Char('h') => {
-                self.prev_row()
+            return Ok(());
+        }
+        if branch.kind == RefKind::Tag {
+            app.repo.checkout_tag(&branch.full_name)?;
+            post_checkout(repo, &app.config, old_head)?;
+            if !args.quiet {
+                eprintln!("HEAD is now at tag '{}'", branch.full_name);
             }
-            KeyCode::Right | KeyCode::Down | KeyCode::Char('j') | KeyCode::Char('l') => {
-                self.next_row()
+            return Ok(());
+        }
+        let local = match &branch.remote {
+            None => {
+                app.repo.checkout_branch(&branch.full_name)?;
+                if !args.quiet {
+                    eprintln!("Switched to branch '{}'", branch.full_name);
+                }
+                branch.full_name.clone()
+            }
+            Some(remote) => {
+                let (local, created) = app.repo.checkout_tracking(&branch.full_name, remote)?;
+                if !args.quiet && created {
+                    eprintln!(
+                        "Switched to a new branch '{local}', tracking '{}'",
+                        branch.full_name
+                    );
+                } else if !args.quiet {
+                    // There was already a local branch of that name.
+                    eprintln!("Switched to branch '{local}'");
+                }
+                local
+            }
+        };
+        record_checkout(&app.repo, &local);
+        post_checkout(repo, &app.config, old_head)?;
+        if args.pull || app.pull_requested {
+            match app.repo.pull_fast_forward(&local) {
+                Ok(pulled) if !args.quiet => eprintln!("{pulled}"),
+                Ok(_) => {}
+                // The switch worked, so this is only worth reporting, not failing over.
+                Err(e) => eprintln!(
+                    "git-branch-picker: switched to {local}, but couldn't fast-forward it: {e:#}"
+                ),
             }
-            _ => {}
         }
     }
+    Ok(())
+}
 
-    fn exit(&mut self) {
-        self.exit = true;
+/// `git checkout` runs the post-checkout hook, so the picker does too (unless the config
+/// says not to), for setups that rely on it e.g. to install the branch's dependencies.
+fn post_checkout(
+    git: &git2::Repository,
+    config: &Config,
+    old_head: Option<git2::Oid>,
+) -> Result<()> {
+    if config.skip_hooks {
+        return Ok(());
     }
+    git_branch_picker::run_post_checkout_hook(git, old_head)
+}
 
-    fn switch_branch(&mut self) {
-        self.user_switched_branch = true;
+fn check_remote(repo: &Repo, args: &Args) -> Result<()> {
+    match &args.remote {
+        Some(remote) if !repo.remotes.contains(remote) => {
+            anyhow::bail!("there's no remote called {remote}")
+        }
+        _ => Ok(()),
     }
+}
 
-    fn next_row(&mut self) {
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i >= self.repo.branches.len() - 1 {
-                    0
-                } else {
-                    i + 1
+/// `read_branches`, with a spinner up if it takes a moment, since on a slow filesystem
+/// it can be a while before there's a table to show.
+fn scan_branches(
+    terminal: &mut Tui,
+    git: &git2::Repository,
+    config: &Config,
+    exclude: &[glob::Pattern],
+    author: Option<&str>,
+) -> Result<Repo> {
+    let git_dir = git.path();
+    let started = std::time::Instant::now();
+    let (sender, scanned) = std::sync::mpsc::channel();
+    std::thread::scope(|scope| {
+        // A `git2::Repository` can't be shared between threads, so this one gets its own.
+        scope.spawn(move || {
+            let read = git2::Repository::open(git_dir)
+                .map_err(anyhow::Error::from)
+                .and_then(|git| read_branches(&git, config, exclude, author));
+            let _ = sender.send(read);
+        });
+        loop {
+            match scanned.recv_timeout(SPINNER_INTERVAL) {
+                Ok(read) => return read,
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                    anyhow::bail!("reading the branches crashed")
                 }
             }
-            None => 0,
+            terminal.draw(|frame| {
+                let [_, middle, _] = Layout::vertical([
+                    Constraint::Fill(1),
+                    Constraint::Length(1),
+                    Constraint::Fill(1),
+                ])
+                .areas(frame.area());
+                let text = format!("{} Scanning branches…", spinner(started));
+                frame.render_widget(Paragraph::new(text).centered(), middle);
+            })?;
+        }
+    })
+}
+
+/// The TUI draws to stderr rather than stdout, so that stdout only ever has
+/// the branch name printed by `--print`, even while it's being captured by a shell.
+type Tui = ratatui::Terminal<ratatui::backend::CrosstermBackend<std::io::Stderr>>;
+
+fn init_terminal() -> Result<Tui> {
+    // Put the terminal back to normal before any panic message gets printed.
+    // Only for the main thread though: if e.g. a background fetch panics,
+    // the picker carries on (and reports the fetch as failed), so it still needs the terminal.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            restore_terminal();
+        }
+        default_hook(info);
+    }));
+    crossterm::terminal::enable_raw_mode()?;
+    crossterm::execute!(
+        std::io::stderr(),
+        crossterm::terminal::EnterAlternateScreen,
+        crossterm::event::EnableMouseCapture
+    )?;
+    Ok(ratatui::Terminal::new(
+        ratatui::backend::CrosstermBackend::new(std::io::stderr()),
+    )?)
+}
+
+fn restore_terminal() {
+    // Best effort: there's nothing useful to do if the terminal won't reset.
+    let _ = crossterm::execute!(std::io::stderr(), crossterm::event::DisableMouseCapture);
+    // Throw away input that's already arrived, e.g. mouse movement reported just before
+    // capture was turned off, which would otherwise end up typed into the shell.
+    while event::poll(std::time::Duration::ZERO).unwrap_or(false) {
+        let _ = event::read();
+    }
+    let _ = crossterm::execute!(
+        std::io::stderr(),
+        crossterm::terminal::LeaveAlternateScreen,
+        crossterm::cursor::Show
+    );
+    // Raw mode goes last, so none of that input got echoed.
+    let _ = crossterm::terminal::disable_raw_mode();
+}
+
+/// How long a pause in typing ends `Action::JumpTo`.
+const TYPE_AHEAD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How long the footer says that something worked, e.g. "Deleted foo".
+const SUCCESS_NOTICE_DURATION: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// How often "3 minutes ago" and the like get recalculated while the picker's open.
+const TIME_REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Something a key can be bound to in the branch list.
+/// Named in the config file's `[keys]` table in snake_case, e.g. `next_row = ["j", "Down"]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    Checkout,
+    CheckoutAndPull,
+    CheckoutDetached,
+    NextRow,
+    PrevRow,
+    FirstRow,
+    LastRow,
+    PageDown,
+    PageUp,
+    NextColumn,
+    PrevColumn,
+    Filter,
+    JumpTo,
+    CycleNamespace,
+    CycleRemotes,
+    ToggleTags,
+    Fetch,
+    ToggleSpecial,
+    CycleSort,
+    SortByColumn,
+    ToggleDateMode,
+    ToggleDateStyle,
+    TogglePreview,
+    ShowCommit,
+    ToggleHash,
+    NewBranch,
+    CreateBranch,
+    Rename,
+    Delete,
+    ToggleMark,
+    DeleteMarked,
+    DeleteMerged,
+    Copy,
+    CyclePalette,
+    Help,
+    Quit,
+}
+
+impl Action {
+    /// Every action, in the order `?` lists them.
+    const ALL: [Action; 36] = [
+        Self::Checkout,
+        Self::CheckoutAndPull,
+        Self::CheckoutDetached,
+        Self::NextRow,
+        Self::PrevRow,
+        Self::FirstRow,
+        Self::LastRow,
+        Self::PageDown,
+        Self::PageUp,
+        Self::NextColumn,
+        Self::PrevColumn,
+        Self::Filter,
+        Self::JumpTo,
+        Self::CycleNamespace,
+        Self::CycleRemotes,
+        Self::ToggleTags,
+        Self::Fetch,
+        Self::ToggleSpecial,
+        Self::CycleSort,
+        Self::SortByColumn,
+        Self::ToggleDateMode,
+        Self::ToggleDateStyle,
+        Self::TogglePreview,
+        Self::ShowCommit,
+        Self::ToggleHash,
+        Self::NewBranch,
+        Self::CreateBranch,
+        Self::Rename,
+        Self::Delete,
+        Self::ToggleMark,
+        Self::DeleteMarked,
+        Self::DeleteMerged,
+        Self::Copy,
+        Self::CyclePalette,
+        Self::Help,
+        Self::Quit,
+    ];
+
+    /// What today's users are used to.
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Self::Checkout => &["Enter"],
+            Self::CheckoutAndPull => &["p"],
+            Self::CheckoutDetached => &["o"],
+            Self::NextRow => &["j", "Down"],
+            Self::PrevRow => &["k", "Up"],
+            Self::FirstRow => &["g", "Home"],
+            Self::LastRow => &["G", "End"],
+            Self::PageDown => &["ctrl-d", "PageDown"],
+            Self::PageUp => &["ctrl-u", "PageUp"],
+            Self::NextColumn => &["l", "Right"],
+            Self::PrevColumn => &["h", "Left"],
+            Self::Filter => &["/"],
+            Self::JumpTo => &["'"],
+            Self::CycleNamespace => &["n"],
+            Self::CycleRemotes => &["r"],
+            Self::ToggleTags => &["Tab"],
+            Self::Fetch => &["f"],
+            Self::ToggleSpecial => &["H"],
+            Self::CycleSort => &["s"],
+            Self::SortByColumn => &["S"],
+            Self::ToggleDateMode => &["a"],
+            Self::ToggleDateStyle => &["t"],
+            Self::TogglePreview => &["v"],
+            Self::ShowCommit => &["i"],
+            Self::ToggleHash => &["#"],
+            Self::NewBranch => &["b"],
+            Self::CreateBranch => &["c"],
+            Self::Rename => &["R"],
+            Self::Delete => &["d"],
+            Self::ToggleMark => &["space"],
+            Self::DeleteMarked => &["D"],
+            Self::DeleteMerged => &["M"],
+            Self::Copy => &["y"],
+            Self::CyclePalette => &["C"],
+            Self::Help => &["?"],
+            Self::Quit => &["q", "Esc"],
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Checkout => "switch to the selected branch",
+            Self::CheckoutAndPull => "switch to it, then fast-forward it from upstream",
+            Self::CheckoutDetached => "check out its commit with a detached HEAD",
+            Self::NextRow => "select the next branch",
+            Self::PrevRow => "select the previous branch",
+            Self::FirstRow => "jump to the first branch",
+            Self::LastRow => "jump to the last branch",
+            Self::PageDown => "move a page down",
+            Self::PageUp => "move a page up",
+            Self::NextColumn => "highlight the next column",
+            Self::PrevColumn => "highlight the previous column",
+            Self::Filter => "filter branches by name",
+            Self::JumpTo => {
+                "jump to a branch by typing the start of its name (unbound letters jump without it)"
+            }
+            Self::CycleNamespace => "cycle the namespace filter",
+            Self::CycleRemotes => "cycle which remotes are shown",
+            Self::ToggleTags => "switch between branches and tags",
+            Self::Fetch => "fetch from the remote",
+            Self::ToggleSpecial => "hide/show special branches",
+            Self::CycleSort => "cycle the sort order",
+            Self::SortByColumn => "sort by the highlighted column",
+            Self::ToggleDateMode => "toggle last commit date/branch age",
+            Self::ToggleDateStyle => "toggle relative/absolute dates",
+            Self::TogglePreview => "toggle the commit preview",
+            Self::ShowCommit => "show the tip commit's full message",
+            Self::ToggleHash => "toggle the commit hash column",
+            Self::NewBranch => "new branch off HEAD, and switch to it",
+            Self::CreateBranch => "new branch off HEAD, and stay here",
+            Self::Rename => "rename the selected branch",
+            Self::Delete => "delete the selected branch",
+            Self::ToggleMark => "mark/unmark the selected branch",
+            Self::DeleteMarked => "delete all marked branches",
+            Self::DeleteMerged => "delete every branch that's merged into HEAD",
+            Self::Copy => "copy the selected branch's name",
+            Self::CyclePalette => "cycle the color palette",
+            Self::Help => "show this help",
+            Self::Quit => "quit",
+        }
+    }
+}
+
+/// A key plus any Ctrl/Alt held with it, e.g. `ctrl-d`, `PageDown` or `G`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    fn from_event(key_event: KeyEvent) -> Self {
+        // Shift is already part of the char, e.g. 'G' rather than shift-'g'.
+        let mut modifiers = key_event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if !matches!(key_event.code, KeyCode::Char(_)) {
+            modifiers |= key_event.modifiers & KeyModifiers::SHIFT;
+        }
+        Self {
+            code: key_event.code,
+            modifiers,
+        }
+    }
+}
+
+impl std::str::FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut key = s;
+        // A lone "-" is the minus key, not a modifier.
+        while let Some((modifier, rest)) = key.split_once('-').filter(|(_, rest)| !rest.is_empty())
+        {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{modifier}' in key '{s}'")),
+            };
+            key = rest;
+        }
+        let mut chars = key.chars();
+        let mut code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                _ => return Err(format!("unknown key '{s}'")),
+            },
+        };
+        // Like `from_event`, so that `shift-a` matches what pressing it sends: 'A'.
+        if let KeyCode::Char(c) = code {
+            if modifiers.contains(KeyModifiers::SHIFT) {
+                modifiers -= KeyModifiers::SHIFT;
+                code = KeyCode::Char(c.to_uppercase().next().unwrap_or(c));
+            }
+        }
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl std::fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl-"),
+            (KeyModifiers::ALT, "alt-"),
+            (KeyModifiers::SHIFT, "shift-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            code => write!(f, "{code}"),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for KeyBinding {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        key.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Which keys do what in the branch list: the defaults,
+/// except for actions the config file gives its own keys.
+#[derive(Debug)]
+struct Keymap {
+    bindings: Vec<(KeyBinding, Action)>,
+}
+
+impl Keymap {
+    fn new(configured: &std::collections::BTreeMap<Action, Vec<KeyBinding>>) -> Self {
+        let mut bindings = Vec::new();
+        for action in Action::ALL {
+            match configured.get(&action) {
+                Some(keys) => bindings.extend(keys.iter().map(|&key| (key, action))),
+                None => bindings.extend(action.default_keys().iter().map(|key| {
+                    (
+                        key.parse().expect("default key bindings should parse"),
+                        action,
+                    )
+                })),
+            }
+        }
+        // A key the user chose wins over any default it clashes with.
+        bindings.sort_by_key(|(_, action)| !configured.contains_key(action));
+        Self { bindings }
+    }
+
+    fn action(&self, key_event: KeyEvent) -> Option<Action> {
+        let pressed = KeyBinding::from_event(key_event);
+        self.bindings
+            .iter()
+            .find(|(key, _)| *key == pressed)
+            .map(|&(_, action)| action)
+    }
+
+    /// e.g. "j, Down". Leaves out keys taken over by another action.
+    fn keys_for(&self, action: Action) -> String {
+        let keys: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .filter(|(key, _)| {
+                self.bindings.iter().find(|(k, _)| k == key).map(|b| b.1) == Some(action)
+            })
+            .map(|(key, _)| key.to_string())
+            .collect();
+        keys.join(", ")
+    }
+}
+
+/// How many commits the preview pane shows.
+const PREVIEW_COMMITS: usize = 10;
+
+/// What the date column shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DateMode {
+    LastCommit,
+    BranchAge,
+}
+
+impl DateMode {
+    fn toggle(self) -> Self {
+        match self {
+            Self::LastCommit => Self::BranchAge,
+            Self::BranchAge => Self::LastCommit,
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Self::LastCommit => "Last commit date",
+            Self::BranchAge => "Branch age",
+        }
+    }
+}
+
+/// A column of the branch table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Column {
+    Name,
+    /// Commits ahead of/behind upstream.
+    AheadBehind,
+    Msg,
+    /// Who made the last commit.
+    Author,
+    Date,
+    /// The tip commit's abbreviated id.
+    Hash,
+    /// How many commits the branch has that main/master doesn't.
+    Commits,
+    /// The branch's open pull request, see `--github`.
+    PullRequest,
+}
+
+impl Column {
+    /// What this column shows for the branch.
+    fn cell(self, branch: &Branch, date_mode: DateMode, date_style: DateStyle) -> String {
+        match self {
+            Self::Name => branch.name.clone(),
+            Self::AheadBehind => branch
+                .ahead_behind
+                .map(|(ahead, behind)| format!("↑{ahead} ↓{behind}"))
+                .unwrap_or_default(),
+            Self::Msg => branch
+                .last_commit
+                .as_ref()
+                .map(|c| c.msg.clone())
+                .unwrap_or_default(),
+            Self::Author => branch
+                .last_commit
+                .as_ref()
+                .map(|c| c.author.clone())
+                .unwrap_or_default(),
+            Self::Hash => branch
+                .last_commit
+                .as_ref()
+                .map(|c| c.short_id.clone())
+                .unwrap_or_default(),
+            Self::Commits => match branch.unique_commits {
+                // The count stopped there, there could be more.
+                Some(n) if n >= git_branch_picker::BRANCH_AGE_WALK_LIMIT => format!("{n}+"),
+                Some(n) => n.to_string(),
+                None => String::new(),
+            },
+            Self::PullRequest => branch
+                .pull_request
+                .as_ref()
+                .map(|pr| format!("#{}", pr.number))
+                .unwrap_or_default(),
+            Self::Date => {
+                let time = match date_mode {
+                    DateMode::LastCommit => branch.last_commit.as_ref().map(|c| &c.time),
+                    DateMode::BranchAge => branch.age.as_ref(),
+                };
+                let shown = time.map(|time| time.show(date_style)).unwrap_or_default();
+                // Like the commits column's "1000+": the walk stopped there,
+                // so the branch is older than that.
+                let capped = date_mode == DateMode::BranchAge
+                    && branch
+                        .unique_commits
+                        .is_some_and(|n| n >= git_branch_picker::BRANCH_AGE_WALK_LIMIT);
+                match (capped, date_style) {
+                    (false, _) => shown.to_owned(),
+                    (true, DateStyle::Relative) => format!("over {shown}"),
+                    (true, DateStyle::Absolute) => format!("before {shown}"),
+                }
+            }
+        }
+    }
+
+    fn header(self, date_mode: DateMode) -> &'static str {
+        match self {
+            Self::Name => "Name",
+            Self::AheadBehind => "↑↓",
+            Self::Msg => "Last commit msg",
+            Self::Author => "Author",
+            Self::Hash => "Hash",
+            Self::Commits => "Commits",
+            Self::PullRequest => "PR",
+            Self::Date => date_mode.header(),
+        }
+    }
+
+    /// How wide the column needs to be to fit its header and its widest cell.
+    fn width(self, sizes: ConstraintSizes, date_mode: DateMode) -> u16 {
+        let header = self.header(date_mode).width() as u16;
+        // + 1 is for padding.
+        match self {
+            Self::Name => {
+                let marker = CURRENT_BRANCH_MARKER.width() as u16;
+                sizes.name.max(header) + marker + 1
+            }
+            Self::AheadBehind => sizes.ahead_behind.max(header) + 1,
+            Self::Msg => sizes.msg.max(header) + 1,
+            Self::Author => sizes.author.max(header) + 1,
+            Self::Hash => sizes.hash.max(header) + 1,
+            Self::Commits => sizes.commits.max(header) + 1,
+            Self::PullRequest => sizes.pull_request.max(header) + 1,
+            Self::Date => sizes.date.max(header) + 1,
+        }
+    }
+
+    /// The date column soaks up any spare width, unless other columns come after it.
+    fn constraint(self, sizes: ConstraintSizes, date_mode: DateMode, is_last: bool) -> Constraint {
+        match self {
+            Self::Msg => Constraint::Max(self.width(sizes, date_mode)),
+            Self::Date if is_last => Constraint::Fill(sizes.date),
+            _ => Constraint::Length(self.width(sizes, date_mode)),
+        }
+    }
+}
+
+/// `git_branch_picker::read_branches`, tidied up the way the config says.
+fn read_branches(
+    git: &git2::Repository,
+    config: &Config,
+    exclude: &[glob::Pattern],
+    author: Option<&str>,
+) -> Result<Repo> {
+    let mut repo = match &config.special_branches {
+        Some(special) => git_branch_picker::read_branches_with(git, exclude, special)?,
+        None => git_branch_picker::read_branches_excluding(git, exclude)?,
+    };
+    if let Some(email) = author {
+        repo.retain_authored_by(git, email);
+    }
+    for branch in &mut repo.branches {
+        branch.name = config.abbreviate_branch(branch);
+    }
+    repo.recently_used = History::recently_used(git);
+    repo.sort_branches(SortMode::Recent, config.pin_special_branches);
+    Ok(repo)
+}
+
+/// Remembers that this branch was just switched to, for `SortMode::LastUsed`.
+/// Failing to is only worth a warning, the switch itself worked.
+fn record_checkout(repo: &Repo, name: &str) {
+    let recorded = repo
+        .open()
+        .map_err(anyhow::Error::from)
+        .and_then(|git| History::record(&git, name));
+    if let Err(e) = recorded {
+        eprintln!("git-branch-picker: couldn't save the branch history: {e:#}");
+    }
+}
+
+/// How many of these branches are in each namespace, biggest first.
+fn namespace_counts<'a>(branches: impl Iterator<Item = &'a Branch>) -> Vec<(&'a str, usize)> {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for namespace in branches.filter_map(Branch::namespace) {
+        match counts.iter_mut().find(|(ns, _)| *ns == namespace) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((namespace, 1)),
+        }
+    }
+    counts.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(y.0)));
+    counts
+}
+
+/// Works out the branches' `Details` on another thread, so the table can be shown
+/// straight away and have messages and times fill in as they're ready.
+#[derive(Debug)]
+struct DetailsLoader {
+    /// Which branch (by kind and full name, since indices change when re-sorting) and its details.
+    loaded: std::sync::mpsc::Receiver<(RefKind, String, Details)>,
+}
+
+impl DetailsLoader {
+    /// Loads the branches at these indices of `repo.branches`, in this order.
+    fn start(repo: &Repo, order: Vec<usize>, max_msg_width: usize, date_format: &str) -> Self {
+        let todo: Vec<_> = order
+            .into_iter()
+            .map(|i| &repo.branches[i])
+            .filter(|b| !b.loaded)
+            .map(|b| (b.kind, b.full_name.clone(), b.tip.map(|(tip, _)| tip)))
+            .collect();
+        let git_dir = repo.git_dir.clone();
+        let base = repo.age_base;
+        let head = repo.head;
+        let date_format = date_format.to_owned();
+        let (sender, loaded) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let Ok(git) = git2::Repository::open(git_dir) else {
+                return;
+            };
+            for (kind, name, tip) in todo {
+                let details =
+                    Details::read(&git, tip, kind, base, head, max_msg_width, &date_format);
+                // Stop if the picker's quit, or started over with a new list of branches.
+                if sender.send((kind, name, details)).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { loaded }
+    }
+}
+
+/// Where to run `gh` from, so it can work out which GitHub repo this is.
+fn gh_dir(git: &git2::Repository) -> &std::path::Path {
+    git.workdir().unwrap_or(git.path())
+}
+
+/// Asks GitHub for the repo's open pull requests on another thread, since `gh` can take a while.
+/// Nothing's ever sent if `gh` couldn't say.
+fn load_pull_requests(
+    git: &git2::Repository,
+) -> std::sync::mpsc::Receiver<std::collections::HashMap<String, PullRequest>> {
+    let dir = gh_dir(git).to_owned();
+    let (sender, loaded) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        if let Some(pull_requests) = git_branch_picker::github_pull_requests(&dir) {
+            // The picker may already have quit, that's fine.
+            let _ = sender.send(pull_requests);
+        }
+    });
+    loaded
+}
+
+/// A fetch running on another thread, so the picker stays usable while it waits on the network.
+#[derive(Debug)]
+struct Fetch {
+    remote: String,
+    started: std::time::Instant,
+    done: std::sync::mpsc::Receiver<Result<(), git2::Error>>,
+}
+
+impl Fetch {
+    fn start(git_dir: std::path::PathBuf, remote: String) -> Self {
+        let (sender, done) = std::sync::mpsc::channel();
+        let name = remote.clone();
+        std::thread::spawn(move || {
+            // The picker may already have quit, that's fine.
+            let _ = sender.send(git_branch_picker::fetch_remote(&git_dir, &name));
+        });
+        Self {
+            remote,
+            started: std::time::Instant::now(),
+            done,
+        }
+    }
+
+    fn spinner(&self) -> char {
+        spinner(self.started)
+    }
+}
+
+/// How often the spinners move.
+const SPINNER_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// The spinner's frame for something that's been going since `started`.
+fn spinner(started: std::time::Instant) -> char {
+    const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+    let frame = started.elapsed().as_millis() / SPINNER_INTERVAL.as_millis();
+    FRAMES[frame as usize % FRAMES.len()]
+}
+
+/// Which remote-tracking branches are listed alongside the local ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RemoteScope {
+    LocalOnly,
+    All,
+    Only(String),
+}
+
+/// The system clipboard (`arboard::Clipboard` isn't `Debug`).
+struct Clipboard(arboard::Clipboard);
+
+impl std::fmt::Debug for Clipboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Clipboard")
+    }
+}
+
+/// What the keyboard is currently driving.
+#[derive(Debug, Default)]
+enum Mode {
+    #[default]
+    Browse,
+    /// Typing the name of a new branch to create off HEAD,
+    /// and either switch to it or carry on picking.
+    NewBranch { prompt: Prompt, switch: bool },
+    /// Typing a new name for the local branch called `old_name` (looked up again on Enter,
+    /// in case a fetch read the branches again meanwhile). `overwrite` is set once the user's
+    /// been warned the name's taken, so Enter again replaces it.
+    Rename {
+        old_name: String,
+        prompt: Prompt,
+        overwrite: bool,
+    },
+    /// Asking what to do about these uncommitted changes before switching branches.
+    ConfirmDirty(Vec<String>),
+    /// Asking whether to delete the local branch with this name. By name rather than index,
+    /// since a fetch finishing behind the dialog reads the branches again.
+    ConfirmDelete { name: String, merged: bool },
+    /// Asking whether to delete all these marked branches (and whether each is merged),
+    /// and how many other marked ones can't be deleted.
+    ConfirmBulkDelete {
+        branches: Vec<(String, bool)>,
+        skipped: usize,
+    },
+    /// Asking whether to really switch to the selected branch, because it's main/master etc.
+    ConfirmSpecial,
+    /// Showing the key bindings.
+    Help,
+    /// Showing the selected branch's tip commit in full,
+    /// scrolled down this many lines.
+    ShowCommit {
+        commit: git_branch_picker::FullCommit,
+        scroll: u16,
+        /// How far it can be scrolled, once it's been wrapped to fit. Updated each draw.
+        max_scroll: u16,
+    },
+    /// Explaining that the selected branch can't be switched to
+    /// because it's checked out in the worktree at this path.
+    InOtherWorktree(std::path::PathBuf),
+}
+
+/// A one-line text input, plus the reason the last submission was rejected.
+#[derive(Debug, Default)]
+struct Prompt {
+    input: String,
+    error: Option<String>,
+}
+
+impl Prompt {
+    /// Edits the input. Returns true if the key was Enter, i.e. the user wants to submit.
+    fn handle_key(&mut self, key_event: KeyEvent) -> bool {
+        match key_event.code {
+            KeyCode::Enter => return true,
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            _ => {}
+        }
+        self.error = None;
+        false
+    }
+}
+
+#[derive(Debug)]
+struct App {
+    repo: Repo,
+    config: Config,
+    keymap: Keymap,
+    exit: bool,
+    /// Exited with Ctrl-C, so nothing gets switched to or saved.
+    interrupted: bool,
+    mode: Mode,
+    /// Maps each visible row to its index in `repo.branches`.
+    filtered_indices: Vec<usize>,
+    /// Only show branches whose names fuzzily match this.
+    /// While it's set, typing edits it instead of triggering key bindings.
+    filter: Option<String>,
+    /// What's been typed since `JumpTo`, and when the last of it was. Like `filter`, typing goes
+    /// here instead of to key bindings while it's set, until a pause of `TYPE_AHEAD_TIMEOUT`.
+    type_ahead: Option<(String, std::time::Instant)>,
+    /// The filter also matches the last commit message. Tab toggles it while filtering.
+    filter_messages: bool,
+    /// Only show branches in this namespace.
+    namespace_filter: Option<String>,
+    remote_scope: RemoteScope,
+    state: TableState,
+    scroll_state: ScrollbarState,
+    colors: TableColors,
+    longest_item_lens: ConstraintSizes,
+    /// Which of `palettes` the colors come from.
+    color_index: usize,
+    palettes: Vec<PaletteName>,
+    /// Don't use any colors of our own, see `TableColors::monochrome`.
+    monochrome: bool,
+    date_mode: DateMode,
+    date_style: DateStyle,
+    sort_mode: SortMode,
+    columns: Vec<Column>,
+    /// `columns`, minus any that didn't fit on screen last time the table was drawn.
+    shown_columns: Vec<Column>,
+    /// Where the table was last drawn, for paging and for working out which row was clicked.
+    table_area: Rect,
+    /// The row last clicked and when, to spot double-clicks.
+    last_click: Option<(usize, std::time::Instant)>,
+    /// Exit without checking the picked branch out, `main` will print it instead.
+    print_only: bool,
+    /// `--cd`: branches checked out in other worktrees are gone to rather than asked about.
+    cd: bool,
+    /// Moving past the last row goes back to the first, and vice versa.
+    wrap_around: bool,
+    /// A one-off message for the footer, e.g. the result of the last action.
+    notice: Option<String>,
+    /// Kept for re-reading the branches, e.g. after a fetch.
+    exclude: Vec<glob::Pattern>,
+    /// `--mine`'s email address, likewise.
+    author: Option<String>,
+    fetch: Option<Fetch>,
+    /// Until it's done, some branches are still missing their message and age.
+    details_loader: Option<DetailsLoader>,
+    /// For the pull request column, waiting on `gh`.
+    pull_request_loader:
+        Option<std::sync::mpsc::Receiver<std::collections::HashMap<String, PullRequest>>>,
+    /// What `gh` found, kept for when the branches are read again.
+    pull_requests: std::collections::HashMap<String, PullRequest>,
+    /// Local branches marked for bulk deletion. By name rather than index,
+    /// so that re-sorting or deleting branches doesn't mark the wrong ones.
+    /// Tags and remote branches can have the same names, so check with `is_marked`.
+    marked: std::collections::HashSet<String>,
+    /// Leave main/master etc. out of the list.
+    hide_special: bool,
+    /// List tags instead of branches.
+    show_tags: bool,
+    /// Show the latest commits on the selected branch under the table.
+    show_preview: bool,
+    /// Commits for the preview, by `Branch::refname`, so they're only looked up once.
+    preview_cache: std::collections::HashMap<String, Vec<PreviewCommit>>,
+    /// When the relative times were last brought up to date.
+    times_refreshed_at: std::time::Instant,
+    /// Some notices also go away on their own, at this time.
+    notice_expires: Option<std::time::Instant>,
+    /// Opened on first use, and kept open because on some platforms
+    /// the copied text goes away when it's dropped.
+    clipboard: Option<Clipboard>,
+    /// If true, run the git checkout command when the TUI exits.
+    user_switched_branch: bool,
+    /// If true, stash uncommitted changes before that checkout.
+    stash_before_checkout: bool,
+    /// If true, fast-forward the branch from upstream after that checkout (as if `--pull`).
+    pull_requested: bool,
+    /// If true, check out the branch's commit rather than the branch, detaching HEAD.
+    detach_requested: bool,
+    /// If set, create this branch off HEAD and switch to it when the TUI exits.
+    new_branch: Option<String>,
+    /// If set, print this worktree's path when the TUI exits, e.g. for `cd`.
+    worktree_to_print: Option<std::path::PathBuf>,
+}
+
+#[derive(Debug)]
+struct TableColors {
+    buffer_bg: Color,
+    header_bg: Color,
+    header_fg: Color,
+    row_fg: Color,
+    selected_row_style_fg: Color,
+    selected_column_style_fg: Color,
+    selected_cell_style_fg: Color,
+    normal_row_color: Color,
+    unusual_row_color: Color,
+    /// Extra styling for special branches, for when there's no `unusual_row_color` to tell them apart.
+    unusual_row_modifier: Modifier,
+    /// Row backgrounds for branches that have diverged a little, more, and a lot from upstream.
+    /// Stops well short of the palette's bright end so the text stays readable.
+    diverged_row_colors: [Color; 3],
+    /// Date text for commits from the last day, week, month, and before that, for `fade_old_dates`.
+    /// Greyer the older they get.
+    date_fgs: [Color; 4],
+    filter_match_fg: Color,
+    current_branch_fg: Color,
+    remote_row_fg: Color,
+    remote_row_modifier: Modifier,
+    footer_border_color: Color,
+    notice_fg: Color,
+    warning_fg: Color,
+    error_fg: Color,
+}
+
+impl TableColors {
+    /// Background for a branch that's `ahead + behind` commits away from its upstream.
+    fn divergence_color(&self, ahead_behind: Option<(usize, usize)>) -> Color {
+        match ahead_behind.map(|(ahead, behind)| ahead + behind) {
+            None | Some(0) => self.normal_row_color,
+            Some(1..=2) => self.diverged_row_colors[0],
+            Some(3..=9) => self.diverged_row_colors[1],
+            Some(_) => self.diverged_row_colors[2],
+        }
+    }
+
+    /// Text color for a date this many seconds ago.
+    fn date_color(&self, seconds_ago: i64) -> Color {
+        const DAY: i64 = 24 * 60 * 60;
+        match seconds_ago / DAY {
+            ..=0 => self.date_fgs[0],
+            1..=6 => self.date_fgs[1],
+            7..=29 => self.date_fgs[2],
+            _ => self.date_fgs[3],
+        }
+    }
+
+    const fn new(color: &tailwind::Palette) -> Self {
+        Self {
+            buffer_bg: tailwind::SLATE.c950,
+            header_bg: color.c900,
+            header_fg: tailwind::SLATE.c200,
+            row_fg: tailwind::SLATE.c200,
+            selected_row_style_fg: color.c400,
+            selected_column_style_fg: color.c400,
+            selected_cell_style_fg: color.c600,
+            normal_row_color: tailwind::SLATE.c950,
+            unusual_row_color: tailwind::SLATE.c800,
+            unusual_row_modifier: Modifier::empty(),
+            diverged_row_colors: [color.c950, color.c900, color.c800],
+            date_fgs: [
+                color.c300,
+                tailwind::SLATE.c300,
+                tailwind::SLATE.c400,
+                tailwind::SLATE.c600,
+            ],
+            filter_match_fg: color.c300,
+            current_branch_fg: color.c400,
+            remote_row_fg: tailwind::SLATE.c500,
+            remote_row_modifier: Modifier::empty(),
+            footer_border_color: color.c400,
+            notice_fg: tailwind::AMBER.c300,
+            warning_fg: tailwind::AMBER.c400,
+            error_fg: tailwind::RED.c400,
+        }
+    }
+
+    /// The terminal's own colors, for `NO_COLOR` or `--no-color`.
+    /// The selection is still shown in reverse video.
+    const fn monochrome() -> Self {
+        Self {
+            buffer_bg: Color::Reset,
+            header_bg: Color::Reset,
+            header_fg: Color::Reset,
+            row_fg: Color::Reset,
+            selected_row_style_fg: Color::Reset,
+            selected_column_style_fg: Color::Reset,
+            selected_cell_style_fg: Color::Reset,
+            normal_row_color: Color::Reset,
+            unusual_row_color: Color::Reset,
+            unusual_row_modifier: Modifier::BOLD,
+            diverged_row_colors: [Color::Reset; 3],
+            date_fgs: [Color::Reset; 4],
+            filter_match_fg: Color::Reset,
+            current_branch_fg: Color::Reset,
+            remote_row_fg: Color::Reset,
+            remote_row_modifier: Modifier::DIM,
+            footer_border_color: Color::Reset,
+            notice_fg: Color::Reset,
+            warning_fg: Color::Reset,
+            error_fg: Color::Reset,
+        }
+    }
+}
+
+impl App {
+    /// `author` is `--mine`'s email address, see `Args::author`.
+    fn new(repo: Repo, config: Config, args: &Args, author: Option<String>) -> Result<Self> {
+        let color_index = config.palette_index();
+        let palettes = config.palettes();
+        let columns = args.columns();
+        let notice = config.date_format_error.clone();
+        let wrap_around = !args.no_wrap && config.wrap.unwrap_or(true);
+        let mut app = Self {
+            exit: false,
+            interrupted: false,
+            mode: Mode::Browse,
+            filtered_indices: Vec::new(),
+            filter: None,
+            type_ahead: None,
+            filter_messages: false,
+            namespace_filter: None,
+            remote_scope: match &args.remote {
+                Some(remote) => RemoteScope::Only(remote.clone()),
+                None => RemoteScope::LocalOnly,
+            },
+            state: TableState::default().with_selected(0),
+            scroll_state: ScrollbarState::new(repo.branches.len().saturating_sub(1) * ITEM_HEIGHT),
+            colors: TableColors::new(palettes[color_index].palette()),
+            color_index,
+            palettes,
+            monochrome: args.no_color
+                || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()),
+            date_mode: DateMode::LastCommit,
+            date_style: DateStyle::Relative,
+            sort_mode: args.sort.into(),
+            columns: columns.clone(),
+            shown_columns: columns,
+            table_area: Rect::default(),
+            last_click: None,
+            longest_item_lens: ConstraintSizes::calculate(
+                repo.branches.iter(),
+                DateStyle::Relative,
+                config.max_name_width(),
+            ),
+            repo,
+            keymap: Keymap::new(&config.keys),
+            config,
+            print_only: args.print,
+            cd: args.cd,
+            wrap_around,
+            notice,
+            notice_expires: None,
+            times_refreshed_at: std::time::Instant::now(),
+            show_preview: false,
+            hide_special: false,
+            show_tags: false,
+            exclude: args.exclude.clone(),
+            author,
+            fetch: None,
+            details_loader: None,
+            pull_request_loader: None,
+            pull_requests: Default::default(),
+            marked: Default::default(),
+            preview_cache: Default::default(),
+            clipboard: None,
+            user_switched_branch: false,
+            stash_before_checkout: false,
+            pull_requested: false,
+            detach_requested: false,
+            new_branch: None,
+            worktree_to_print: None,
+        };
+        app.repo
+            .sort_branches(app.sort_mode, app.config.pin_special_branches);
+        app.refilter();
+        let row_of = |is_it: &dyn Fn(&Branch) -> bool| {
+            app.filtered_indices
+                .iter()
+                .position(|&i| is_it(&app.repo.branches[i]))
+        };
+        let current = row_of(&|b| b.is_current);
+        let initial = match app.config.initial_selection {
+            InitialSelection::Current => current,
+            InitialSelection::Top => None,
+            InitialSelection::Recent => app
+                .repo
+                .recently_used
+                .iter()
+                .find_map(|name| {
+                    row_of(&|b| b.kind == RefKind::Local && !b.is_current && b.full_name == *name)
+                })
+                .or(current),
+        };
+        if let Some(row) = initial {
+            app.select_row(row);
+        }
+        app.start_loading_details();
+        if app.columns.contains(&Column::PullRequest) {
+            app.pull_request_loader = app.repo.open().ok().map(|git| load_pull_requests(&git));
+        }
+        if args.fetch {
+            app.start_fetch();
+        }
+        Ok(app)
+    }
+
+    fn set_colors(&mut self) {
+        self.colors = if self.monochrome {
+            TableColors::monochrome()
+        } else {
+            TableColors::new(self.palettes[self.color_index].palette())
+        };
+    }
+
+    /// runs the application's main loop until the user quits
+    fn run(&mut self, terminal: &mut Tui) -> Result<()> {
+        while !self.exit {
+            self.clamp_selection();
+            terminal.draw(|frame| self.draw(frame))?;
+            self.handle_events()?;
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        if let Mode::ShowCommit {
+            commit,
+            scroll,
+            max_scroll,
+        } = &mut self.mode
+        {
+            let inner = commit_area(frame.area()).inner(Margin::new(1, 1));
+            let lines = commit_paragraph(commit).line_count(inner.width) as u16;
+            // Only far enough for the last line to reach the bottom.
+            *max_scroll = lines.saturating_sub(inner.height);
+            *scroll = (*scroll).min(*max_scroll);
+        }
+        let footer_lines = self.footer_lines();
+        let preview_height = if self.show_preview {
+            PREVIEW_COMMITS as u16 + 2
+        } else {
+            0
+        };
+        let vertical = &Layout::vertical([
+            Constraint::Min(5),
+            Constraint::Length(preview_height),
+            Constraint::Length(footer_lines.len() as u16 + 2),
+        ]);
+        let rects = vertical.split(frame.area());
+
+        self.set_colors();
+
+        self.render_table(frame, rects[0]);
+        self.render_scrollbar(frame, rects[0]);
+        if self.show_preview {
+            self.render_preview(frame, rects[1]);
+        }
+        self.render_footer(frame, rects[2], footer_lines);
+        match &self.mode {
+            Mode::Browse => {}
+            Mode::NewBranch { prompt, switch } => {
+                let title = if *switch {
+                    "New branch off HEAD"
+                } else {
+                    "Create branch off HEAD"
+                };
+                self.render_prompt(frame, title, prompt)
+            }
+            Mode::Rename {
+                old_name, prompt, ..
+            } => {
+                let title = format!("Rename {old_name}");
+                self.render_prompt(frame, &title, prompt)
+            }
+            Mode::ConfirmDirty(paths) => self.render_confirm_dirty(frame, paths),
+            Mode::ConfirmDelete { name, merged } => {
+                self.render_confirm_delete(frame, name, *merged)
+            }
+            Mode::ConfirmBulkDelete { branches, skipped } => {
+                self.render_confirm_bulk_delete(frame, branches, *skipped)
+            }
+            Mode::Help => self.render_help(frame),
+            Mode::ShowCommit { commit, scroll, .. } => self.render_commit(frame, commit, *scroll),
+            Mode::InOtherWorktree(path) => self.render_in_other_worktree(frame, path),
+            Mode::ConfirmSpecial => self.render_confirm_special(frame),
+        }
+    }
+
+    /// Waits for the next event, or until it's time to update the screen anyway:
+    /// to hide a notice, keep the "3 minutes ago"s current or move the fetch spinner.
+    fn handle_events(&mut self) -> Result<()> {
+        let next_refresh = self.times_refreshed_at + TIME_REFRESH_INTERVAL;
+        let mut wake_at = match self.notice_expires {
+            Some(expires) => expires.min(next_refresh),
+            None => next_refresh,
+        };
+        let now = std::time::Instant::now();
+        if self.fetch.is_some()
+            || self.details_loader.is_some()
+            || self.pull_request_loader.is_some()
+        {
+            wake_at = wake_at.min(now + SPINNER_INTERVAL);
+        }
+        if let Some((_, typed_at)) = &self.type_ahead {
+            wake_at = wake_at.min(*typed_at + TYPE_AHEAD_TIMEOUT);
+        }
+        if !event::poll(wake_at.saturating_duration_since(now))? {
+            // Again, now the wait is over, so whatever it was waiting for is due.
+            let now = std::time::Instant::now();
+            self.check_fetch();
+            self.receive_details();
+            self.receive_pull_requests();
+            if self.notice_expires.is_some_and(|expires| expires <= now) {
+                self.notice = None;
+                self.notice_expires = None;
+            }
+            if next_refresh <= now {
+                self.refresh_times();
+            }
+            self.expire_type_ahead();
+            return Ok(());
+        }
+        match event::read()? {
+            // it's important to check that the event is a key press event as
+            // crossterm also emits key release and repeat events on Windows.
+            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                self.handle_key_event(key_event)
+            }
+            Event::Mouse(mouse_event) if matches!(self.mode, Mode::Browse) => {
+                self.handle_mouse_event(mouse_event)
+            }
+            // Returning is enough to redraw at the new size, which works out the columns
+            // and page size again. A click from before can't be half of a double-click now.
+            Event::Resize(..) => self.last_click = None,
+            _ => {}
+        };
+        Ok(())
+    }
+
+    /// Click selects a row, double-click switches to it, and the wheel scrolls.
+    fn handle_mouse_event(&mut self, mouse_event: MouseEvent) {
+        const DOUBLE_CLICK: std::time::Duration = std::time::Duration::from_millis(400);
+        match mouse_event.kind {
+            MouseEventKind::ScrollDown => self.next_row(),
+            MouseEventKind::ScrollUp => self.prev_row(),
+            MouseEventKind::Down(MouseButton::Left) => {
+                let Some(row) = self.row_at(mouse_event.column, mouse_event.row) else {
+                    return;
+                };
+                self.select_row(row);
+                let now = std::time::Instant::now();
+                let is_double = self
+                    .last_click
+                    .is_some_and(|(last_row, at)| last_row == row && now - at < DOUBLE_CLICK);
+                if is_double {
+                    self.last_click = None;
+                    self.pull_requested = false;
+                    self.detach_requested = false;
+                    self.enter();
+                } else {
+                    self.last_click = Some((row, now));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Which visible row is drawn at this screen position, if any.
+    fn row_at(&self, column: u16, row: u16) -> Option<usize> {
+        let area = self.table_area;
+        // The header takes up a row.
+        let first_row_y = area.y + 1;
+        if column < area.x || column >= area.right() || row < first_row_y || row >= area.bottom() {
+            return None;
+        }
+        let i = self.state.offset() + usize::from(row - first_row_y) / ITEM_HEIGHT;
+        (i < self.filtered_indices.len()).then_some(i)
+    }
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        // Notices only last until the next key press.
+        self.notice = None;
+        self.notice_expires = None;
+        self.expire_type_ahead();
+        // Raw mode turns Ctrl-C into a key press rather than SIGINT, so it's handled here,
+        // in every mode and whatever the keymap says.
+        if key_event.code == KeyCode::Char('c') && key_event.modifiers == KeyModifiers::CONTROL {
+            self.interrupted = true;
+            self.exit = true;
+            return;
+        }
+        match &mut self.mode {
+            Mode::Browse if self.filter.is_some() => self.handle_filter_key(key_event),
+            Mode::Browse if self.type_ahead.is_some() => self.handle_type_ahead_key(key_event),
+            Mode::Browse => self.handle_browse_key(key_event),
+            Mode::NewBranch { prompt, .. } => {
+                if key_event.code == KeyCode::Esc {
+                    self.mode = Mode::Browse;
+                } else if prompt.handle_key(key_event) {
+                    self.submit_new_branch();
+                }
+            }
+            Mode::Rename {
+                prompt, overwrite, ..
+            } => {
+                if key_event.code == KeyCode::Esc {
+                    self.mode = Mode::Browse;
+                } else if prompt.handle_key(key_event) {
+                    self.submit_rename();
+                } else {
+                    // The warning was about the old name.
+                    *overwrite = false;
+                }
+            }
+            Mode::ConfirmDirty(_) => match key_event.code {
+                KeyCode::Char('y') | KeyCode::Enter => self.confirm_switch(),
+                KeyCode::Char('s') => {
+                    self.stash_before_checkout = true;
+                    self.confirm_switch();
+                }
+                KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => self.mode = Mode::Browse,
+                _ => {}
+            },
+            Mode::ConfirmDelete { name, .. } => match key_event.code {
+                KeyCode::Char('y') => {
+                    let name = std::mem::take(name);
+                    self.mode = Mode::Browse;
+                    self.delete_branch(name);
+                }
+                KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => self.mode = Mode::Browse,
+                _ => {}
+            },
+            Mode::ConfirmBulkDelete { branches, .. } => match key_event.code {
+                KeyCode::Char('y') => {
+                    let names = branches.iter().map(|(name, _)| name.clone()).collect();
+                    self.mode = Mode::Browse;
+                    self.delete_branches(names);
+                }
+                KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => self.mode = Mode::Browse,
+                _ => {}
+            },
+            Mode::ConfirmSpecial => match key_event.code {
+                KeyCode::Char('y') | KeyCode::Enter => {
+                    self.mode = Mode::Browse;
+                    self.check_before_switching();
+                }
+                KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => self.mode = Mode::Browse,
+                _ => {}
+            },
+            Mode::InOtherWorktree(path) => match key_event.code {
+                KeyCode::Char('p') => {
+                    self.worktree_to_print = Some(path.clone());
+                    self.exit();
+                }
+                KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => self.mode = Mode::Browse,
+                _ => {}
+            },
+            Mode::Help => {
+                if matches!(
+                    key_event.code,
+                    KeyCode::Char('?') | KeyCode::Char('q') | KeyCode::Esc
+                ) {
+                    self.mode = Mode::Browse;
+                }
+            }
+            Mode::ShowCommit {
+                scroll, max_scroll, ..
+            } => match key_event.code {
+                KeyCode::Char('j') | KeyCode::Down => *scroll = (*scroll + 1).min(*max_scroll),
+                KeyCode::Char('k') | KeyCode::Up => *scroll = scroll.saturating_sub(1),
+                KeyCode::PageDown => *scroll = (*scroll + 10).min(*max_scroll),
+                KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+                KeyCode::Char('i') | KeyCode::Char('q') | KeyCode::Esc => self.mode = Mode::Browse,
+                _ => {}
+            },
+        }
+    }
+
+    /// Letters (and anything else printable) that aren't bound to an action start jumping
+    /// to a branch, as if `'` had been pressed first.
+    fn handle_browse_key(&mut self, key_event: KeyEvent) {
+        let plain = !key_event
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match (self.keymap.action(key_event), key_event.code) {
+            (Some(action), _) => self.update(action),
+            (None, KeyCode::Char(c)) if plain && !c.is_whitespace() => {
+                self.update(Action::JumpTo);
+                self.handle_type_ahead_key(key_event);
+            }
+            _ => {}
+        }
+    }
+
+    /// Does whatever this action does in the branch list. Separate from the key handling,
+    /// so that the picker can be driven without a terminal, e.g. by the tests.
+    fn update(&mut self, action: Action) {
+        // So a `p` that got cancelled in a dialog doesn't linger.
+        self.pull_requested = action == Action::CheckoutAndPull;
+        self.detach_requested = action == Action::CheckoutDetached;
+        match action {
+            Action::Help => self.mode = Mode::Help,
+            Action::Quit => self.exit(),
+            Action::Checkout => self.enter(),
+            Action::CheckoutAndPull | Action::CheckoutDetached => self.switch_branch(),
+            Action::PageDown => self.page_down(),
+            Action::PageUp => self.page_up(),
+            Action::Delete => self.ask_to_delete(),
+            Action::Rename => self.ask_for_new_name(),
+            Action::DeleteMarked => self.ask_to_delete_marked(),
+            Action::DeleteMerged => self.ask_to_delete_merged(),
+            Action::ToggleMark => self.toggle_mark(),
+            Action::PrevRow => self.prev_row(),
+            Action::NextRow => self.next_row(),
+            Action::FirstRow => self.first_row(),
+            Action::LastRow => self.last_row(),
+            Action::PrevColumn => self.prev_column(),
+            Action::NextColumn => self.next_column(),
+            Action::ToggleDateMode => self.date_mode = self.date_mode.toggle(),
+            Action::ToggleDateStyle => self.toggle_date_style(),
+            Action::NewBranch => {
+                self.mode = Mode::NewBranch {
+                    prompt: Prompt::default(),
+                    switch: true,
+                }
+            }
+            Action::CreateBranch => {
+                self.mode = Mode::NewBranch {
+                    prompt: Prompt::default(),
+                    switch: false,
+                }
+            }
+            Action::CycleNamespace => self.cycle_namespace_filter(),
+            Action::CycleRemotes => self.cycle_remote_scope(),
+            Action::Fetch => self.start_fetch(),
+            Action::ToggleTags => self.toggle_tags(),
+            Action::CycleSort => self.set_sort_mode(self.sort_mode.next()),
+            Action::SortByColumn => self.sort_by_column(),
+            Action::Copy => self.copy_selected_name(),
+            Action::TogglePreview => self.show_preview = !self.show_preview,
+            Action::ShowCommit => self.show_commit(),
+            Action::ToggleHash => self.toggle_hash_column(),
+            Action::ToggleSpecial => {
+                self.hide_special = !self.hide_special;
+                self.refilter();
+            }
+            Action::CyclePalette => self.color_index = (self.color_index + 1) % self.palettes.len(),
+            Action::Filter => {
+                self.filter = Some(String::new());
+                self.refilter();
+            }
+            Action::JumpTo => self.type_ahead = Some((String::new(), std::time::Instant::now())),
+        }
+    }
+
+    /// Typing adds to what to jump to. Anything else stops the jumping,
+    /// and then does what it normally would, e.g. Enter to switch to where it jumped to.
+    fn handle_type_ahead_key(&mut self, key_event: KeyEvent) {
+        let Some((typed, typed_at)) = &mut self.type_ahead else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Char(c) if !key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                typed.push(c);
+                *typed_at = std::time::Instant::now();
+                self.jump_to_typed();
+            }
+            KeyCode::Esc => self.type_ahead = None,
+            _ => {
+                self.type_ahead = None;
+                self.handle_browse_key(key_event);
+            }
+        }
+    }
+
+    /// Selects the next row whose name starts with what's been typed (ignoring case), if any.
+    /// The current row counts, unless it's the first letter: that way each `'` and letter
+    /// steps on through the branches starting with it.
+    fn jump_to_typed(&mut self) {
+        let Some((typed, _)) = &self.type_ahead else {
+            return;
+        };
+        let typed = typed.to_lowercase();
+        let rows = self.filtered_indices.len();
+        let current = self.state.selected().unwrap_or(0);
+        let start = if typed.chars().count() == 1 {
+            current + 1
+        } else {
+            current
+        };
+        let found = (0..rows).map(|i| (start + i) % rows).find(|&row| {
+            let branch = &self.repo.branches[self.filtered_indices[row]];
+            branch.name.to_lowercase().starts_with(&typed)
+        });
+        if let Some(row) = found {
+            self.select_row(row);
+        }
+    }
+
+    fn expire_type_ahead(&mut self) {
+        if let Some((_, typed_at)) = &self.type_ahead {
+            if typed_at.elapsed() >= TYPE_AHEAD_TIMEOUT {
+                self.type_ahead = None;
+            }
+        }
+    }
+
+    fn handle_filter_key(&mut self, key_event: KeyEvent) {
+        let Some(filter) = &mut self.filter else {
+            return;
+        };
+        match key_event.code {
+            // Leave filtering, rather than the whole app.
+            KeyCode::Esc => self.filter = None,
+            KeyCode::Enter => return self.enter(),
+            KeyCode::Up => return self.prev_row(),
+            KeyCode::Down => return self.next_row(),
+            KeyCode::Tab => self.filter_messages = !self.filter_messages,
+            KeyCode::Backspace => {
+                filter.pop();
+            }
+            KeyCode::Char(c) => filter.push(c),
+            _ => return,
+        }
+        self.refilter();
+    }
+
+    fn exit(&mut self) {
+        self.exit = true;
+    }
+
+    /// Does whatever `on_enter` says to with the selected branch.
+    fn enter(&mut self) {
+        match self.config.on_enter {
+            OnEnter::Checkout => {}
+            OnEnter::Print => self.print_only = true,
+            OnEnter::Detached => self.detach_requested = true,
+            OnEnter::Preview => {
+                self.show_preview = !self.show_preview;
+                return;
+            }
+        }
+        self.switch_branch();
+    }
+
+    /// Exits to check out the selected branch,
+    /// unless there's something to ask about first.
+    fn switch_branch(&mut self) {
+        if self.print_only {
+            // Nothing's getting checked out, so uncommitted changes don't matter.
+            return self.confirm_switch();
+        }
+        let special = self
+            .selected_branch()
+            .is_some_and(|b| self.repo.is_special(b) && !b.is_current);
+        if special && self.config.confirm_special_checkout {
+            self.mode = Mode::ConfirmSpecial;
+            return;
+        }
+        self.check_before_switching();
+    }
+
+    /// The rest of `switch_branch`, for things that would stop the checkout working.
+    fn check_before_switching(&mut self) {
+        // Git won't have the same branch checked out in two worktrees,
+        // but its commit can be, so that's no problem when detaching.
+        let worktree = self
+            .selected_branch()
+            .filter(|_| !self.detach_requested)
+            .and_then(|b| b.worktree.clone());
+        if let Some(path) = worktree {
+            if self.cd {
+                // No need to ask, `--cd` goes there instead.
+                self.worktree_to_print = Some(path);
+                return self.exit();
+            }
+            self.mode = Mode::InOtherWorktree(path);
+            return;
+        }
+        match self.repo.dirty_paths() {
+            Ok(paths) if !paths.is_empty() => self.mode = Mode::ConfirmDirty(paths),
+            _ => self.confirm_switch(),
+        }
+    }
+
+    fn ask_to_delete(&mut self) {
+        let Some(index) = self.selected_index() else {
+            return;
+        };
+        let branch = &self.repo.branches[index];
+        if self.repo.is_special(branch) {
+            self.notice = Some(format!(
+                "{} is protected, not deleting it",
+                branch.full_name
+            ));
+            return;
+        }
+        if branch.kind != RefKind::Local {
+            self.notice = Some("Only local branches can be deleted".to_owned());
+            return;
+        }
+        match self.repo.is_merged(&branch.full_name) {
+            Ok(merged) => {
+                self.mode = Mode::ConfirmDelete {
+                    name: branch.full_name.clone(),
+                    merged,
+                }
+            }
+            Err(e) => self.notice = Some(e.message().to_owned()),
+        }
+    }
+
+    fn show_commit(&mut self) {
+        let Some(branch) = self.selected_branch() else {
+            return;
+        };
+        let Some((tip, _)) = branch.tip else {
+            self.notice = Some(format!("{} doesn't point at a commit", branch.full_name));
+            return;
+        };
+        match self.repo.full_commit(tip, self.config.date_format()) {
+            Ok(commit) => {
+                self.mode = Mode::ShowCommit {
+                    commit,
+                    scroll: 0,
+                    max_scroll: 0,
+                }
+            }
+            Err(e) => self.notice = Some(format!("Couldn't read the commit: {e:#}")),
+        }
+    }
+
+    fn ask_for_new_name(&mut self) {
+        let Some(branch) = self.selected_branch() else {
+            return;
+        };
+        if branch.kind != RefKind::Local {
+            self.notice = Some("Only local branches can be renamed".to_owned());
+            return;
+        }
+        self.mode = Mode::Rename {
+            old_name: branch.full_name.clone(),
+            prompt: Prompt {
+                input: branch.full_name.clone(),
+                error: None,
+            },
+            overwrite: false,
+        };
+    }
+
+    /// Renames the branch to what was typed, unless that's invalid or taken, in which case
+    /// the prompt stays open showing why. A taken name can be replaced by pressing Enter again.
+    fn submit_rename(&mut self) {
+        let Mode::Rename {
+            old_name,
+            prompt,
+            overwrite,
+        } = &mut self.mode
+        else {
+            return;
+        };
+        let old_name = old_name.clone();
+        let new_name = prompt.input.trim().to_owned();
+        if new_name == old_name {
+            self.mode = Mode::Browse;
+            return;
+        }
+        let Some(index) = self
+            .repo
+            .branches
+            .iter()
+            .position(|b| b.kind == RefKind::Local && b.full_name == old_name)
+        else {
+            prompt.error = Some(format!("{old_name} isn't there any more"));
+            return;
+        };
+        if !git2::Branch::name_is_valid(&new_name).unwrap_or(false) {
+            prompt.error = Some(format!("'{new_name}' isn't a valid branch name"));
+            return;
+        }
+        let taken = self
+            .repo
+            .branches
+            .iter()
+            .position(|b| b.kind == RefKind::Local && b.full_name == new_name);
+        if let Some(taken) = taken {
+            if self.repo.branches[taken].is_current {
+                prompt.error = Some(format!("{new_name} is checked out, it can't be replaced"));
+                return;
+            }
+            if !*overwrite {
+                prompt.error = Some(format!(
+                    "{new_name} already exists. Enter again to replace it"
+                ));
+                *overwrite = true;
+                return;
+            }
+        }
+        match self.rename_branch(index, &old_name, &new_name, taken) {
+            Ok(()) => {
+                self.mode = Mode::Browse;
+                self.report_success(format!("Renamed {old_name} to {new_name}"));
+            }
+            Err(e) => {
+                if let Mode::Rename { prompt, .. } = &mut self.mode {
+                    prompt.error = Some(format!("Couldn't rename {old_name}: {e}"));
+                }
+            }
+        }
+    }
+
+    /// Renames the branch at `index`, replacing the one at `taken` if there is one,
+    /// and keeps it selected.
+    fn rename_branch(
+        &mut self,
+        index: usize,
+        old_name: &str,
+        new_name: &str,
+        taken: Option<usize>,
+    ) -> Result<()> {
+        self.repo
+            .rename_branch(old_name, new_name, taken.is_some())?;
+        let mut branch = self.read_local_branch(new_name)?;
+        branch.worktree = self.repo.branches[index].worktree.take();
+        self.repo.branches[index] = branch;
+        self.load_details_now(index);
+        if let Some(taken) = taken {
+            self.repo.branches.remove(taken);
+            self.preview_cache.remove(&format!("refs/heads/{new_name}"));
+            self.marked.remove(new_name);
+        }
+        if let Some(cached) = self.preview_cache.remove(&format!("refs/heads/{old_name}")) {
+            self.preview_cache
+                .insert(format!("refs/heads/{new_name}"), cached);
+        }
+        if self.marked.remove(old_name) {
+            self.marked.insert(new_name.to_owned());
+        }
+        self.repo
+            .sort_branches(self.sort_mode, self.config.pin_special_branches);
+        self.refilter();
+        self.select_branch_named(new_name);
+        Ok(())
+    }
+
+    /// Marks or unmarks the selected branch for bulk deletion, then moves on to the next one.
+    fn toggle_mark(&mut self) {
+        let Some(branch) = self.selected_branch() else {
+            return;
+        };
+        if branch.kind != RefKind::Local {
+            self.notice = Some("Only local branches can be marked for deletion".to_owned());
+            return;
+        }
+        let name = branch.full_name.clone();
+        if !self.marked.remove(&name) {
+            self.marked.insert(name);
+        }
+        self.next_row();
+    }
+
+    fn is_marked(&self, branch: &Branch) -> bool {
+        branch.kind == RefKind::Local && self.marked.contains(&branch.full_name)
+    }
+
+    /// Like `ask_to_delete`, but for every marked branch that can be deleted.
+    fn ask_to_delete_marked(&mut self) {
+        if self.marked.is_empty() {
+            self.notice = Some("Mark branches to delete with space first".to_owned());
+            return;
+        }
+        let mut skipped = 0;
+        let mut to_delete = Vec::new();
+        for branch in &self.repo.branches {
+            if !self.is_marked(branch) {
+                continue;
+            }
+            let deletable = !self.repo.is_special(branch)
+                && !branch.is_current
+                && branch.kind == RefKind::Local;
+            match self.repo.is_merged(&branch.full_name) {
+                Ok(merged) if deletable => to_delete.push((branch.full_name.clone(), merged)),
+                _ => skipped += 1,
+            }
+        }
+        if to_delete.is_empty() {
+            self.notice = Some("None of the marked branches can be deleted".to_owned());
+            return;
+        }
+        self.mode = Mode::ConfirmBulkDelete {
+            branches: to_delete,
+            skipped,
+        };
+    }
+
+    /// Like `ask_to_delete_marked`, for every local branch that's merged into HEAD,
+    /// apart from main/master etc. and ones checked out in other worktrees.
+    fn ask_to_delete_merged(&mut self) {
+        let to_delete: Vec<(String, bool)> = self
+            .repo
+            .branches
+            .iter()
+            .filter(|b| b.kind == RefKind::Local && b.worktree.is_none())
+            .filter(|b| !self.repo.is_special(b))
+            // The details loader may not have got to them all yet.
+            .filter(|b| {
+                if b.loaded {
+                    b.merged
+                } else {
+                    !b.is_current && self.repo.is_merged(&b.full_name).unwrap_or(false)
+                }
+            })
+            .map(|b| (b.full_name.clone(), true))
+            .collect();
+        if to_delete.is_empty() {
+            self.notice = Some("No merged branches to delete".to_owned());
+            return;
+        }
+        self.mode = Mode::ConfirmBulkDelete {
+            branches: to_delete,
+            skipped: 0,
+        };
+    }
+
+    fn delete_branches(&mut self, names: Vec<String>) {
+        let selected = self.selected_branch().map(|b| b.full_name.clone());
+        let mut deleted = 0;
+        let mut failures = Vec::new();
+        for name in names {
+            match self.repo.delete_branch(&name) {
+                Ok(()) => {
+                    deleted += 1;
+                    self.repo
+                        .branches
+                        .retain(|b| b.kind != RefKind::Local || b.full_name != name);
+                    self.preview_cache.remove(&format!("refs/heads/{name}"));
+                    self.marked.remove(&name);
+                }
+                Err(e) => failures.push(format!("{name} ({})", e.message())),
+            }
+        }
+        self.refilter();
+        // If the selected branch is still there, stay on it rather than on its row.
+        if let Some(selected) = selected {
+            self.select_branch_named(&selected);
+        }
+        if failures.is_empty() {
+            self.report_success(format!("Deleted {}", count_branches(deleted)));
+        } else {
+            self.notice = Some(format!(
+                "Deleted {}, couldn't delete {}",
+                count_branches(deleted),
+                failures.join(", ")
+            ));
+        }
+    }
+
+    fn delete_branch(&mut self, name: String) {
+        if let Err(e) = self.repo.delete_branch(&name) {
+            self.notice = Some(format!("Couldn't delete {name}: {}", e.message()));
+            return;
+        }
+        self.repo
+            .branches
+            .retain(|b| b.kind != RefKind::Local || b.full_name != name);
+        self.preview_cache.remove(&format!("refs/heads/{name}"));
+        self.marked.remove(&name);
+        self.refilter();
+        self.report_success(format!("Deleted {name}"));
+    }
+
+    /// Unlike other notices, which stay until the next key press, this one also goes away
+    /// by itself after a moment: it's only confirming what was asked for.
+    fn report_success(&mut self, message: String) {
+        self.notice = Some(message);
+        self.notice_expires = Some(std::time::Instant::now() + SUCCESS_NOTICE_DURATION);
+    }
+
+    fn refresh_times(&mut self) {
+        self.times_refreshed_at = std::time::Instant::now();
+        let mut changed = false;
+        for branch in &mut self.repo.branches {
+            let times = branch.last_commit.iter_mut().map(|c| &mut c.time);
+            for time in times.chain(branch.age.as_mut()) {
+                changed |= time.refresh();
+            }
+        }
+        if changed {
+            self.longest_item_lens = ConstraintSizes::calculate(
+                self.scoped_branches(),
+                self.date_style,
+                self.config.max_name_width(),
+            );
+            self.preview_cache.clear();
+        }
+    }
+
+    /// Copies the selected branch's real name (not the shortened one) to the clipboard.
+    fn copy_selected_name(&mut self) {
+        let Some(name) = self.selected_branch().map(|b| b.full_name.clone()) else {
+            return;
+        };
+        let copied = match &mut self.clipboard {
+            Some(Clipboard(clipboard)) => clipboard.set_text(&name),
+            None => arboard::Clipboard::new().and_then(|clipboard| {
+                self.clipboard
+                    .insert(Clipboard(clipboard))
+                    .0
+                    .set_text(&name)
+            }),
+        };
+        match copied {
+            Ok(()) => {
+                self.report_success(format!("Copied {name}!"));
+            }
+            Err(e) => self.notice = Some(format!("Couldn't copy {name}: {e}")),
+        }
+    }
+
+    fn confirm_switch(&mut self) {
+        self.user_switched_branch = true;
+        self.exit();
+    }
+
+    /// Exits to create the typed branch (or creates it right away, if not switching to it),
+    /// unless it's invalid or already taken, in which case the prompt stays open showing why.
+    fn submit_new_branch(&mut self) {
+        let Mode::NewBranch { prompt, switch } = &mut self.mode else {
+            return;
+        };
+        let name = prompt.input.trim().to_owned();
+        if let Err(e) = validate_new_branch_name(&self.repo, &name) {
+            prompt.error = Some(e);
+            return;
+        }
+        if *switch {
+            self.new_branch = Some(name);
+            return self.exit();
+        }
+        match self.create_branch(&name) {
+            Ok(()) => {
+                self.mode = Mode::Browse;
+                self.report_success(format!("Created {name}"));
+            }
+            Err(e) => {
+                if let Mode::NewBranch { prompt, .. } = &mut self.mode {
+                    prompt.error = Some(format!("Couldn't create {name}: {e}"));
+                }
+            }
+        }
+    }
+
+    /// Makes a branch at HEAD and adds it to the list, selected.
+    fn create_branch(&mut self, name: &str) -> Result<()> {
+        self.repo.create_branch_at_head(name)?;
+        let branch = self.read_local_branch(name)?;
+        self.repo.branches.push(branch);
+        self.load_details_now(self.repo.branches.len() - 1);
+        self.repo
+            .sort_branches(self.sort_mode, self.config.pin_special_branches);
+        self.refilter();
+        self.select_branch_named(name);
+        Ok(())
+    }
+
+    /// Starts loading every branch's `Details` in the background, the shown rows nearest
+    /// the selection first since they're the ones on screen.
+    fn start_loading_details(&mut self) {
+        let selected = self.state.selected().unwrap_or(0);
+        let mut rows: Vec<(usize, usize)> = self
+            .filtered_indices
+            .iter()
+            .enumerate()
+            .map(|(row, &i)| (row.abs_diff(selected), i))
+            .collect();
+        rows.sort();
+        let mut order: Vec<usize> = rows.into_iter().map(|(_, i)| i).collect();
+        let shown: std::collections::HashSet<usize> = order.iter().copied().collect();
+        order.extend((0..self.repo.branches.len()).filter(|i| !shown.contains(i)));
+        self.details_loader = Some(DetailsLoader::start(
+            &self.repo,
+            order,
+            self.config.max_message_width(),
+            self.config.date_format(),
+        ));
+    }
+
+    /// Fills in whatever details the `DetailsLoader` has finished with since last time.
+    fn receive_details(&mut self) {
+        let Some(loader) = &self.details_loader else {
+            return;
+        };
+        let mut received = false;
+        loop {
+            match loader.loaded.try_recv() {
+                Ok((kind, name, details)) => {
+                    let branch = self
+                        .repo
+                        .branches
+                        .iter_mut()
+                        .find(|b| b.kind == kind && b.full_name == name && !b.loaded);
+                    if let Some(branch) = branch {
+                        branch.set_details(details);
+                        received = true;
+                    }
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => break,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    self.details_loader = None;
+                    break;
+                }
+            }
+        }
+        if !received {
+            return;
+        }
+        if self.filter_messages && self.filter.is_some() {
+            // More branches' messages to match against.
+            self.refilter();
+        } else {
+            // Columns may widen a little as more gets loaded.
+            self.longest_item_lens = ConstraintSizes::calculate(
+                self.scoped_branches(),
+                self.date_style,
+                self.config.max_name_width(),
+            );
+        }
+    }
+
+    /// Fills in the pull request column once `gh` has answered.
+    fn receive_pull_requests(&mut self) {
+        let Some(loader) = &self.pull_request_loader else {
+            return;
+        };
+        match loader.try_recv() {
+            Ok(pull_requests) => {
+                self.pull_request_loader = None;
+                self.pull_requests = pull_requests;
+                self.repo.set_pull_requests(&self.pull_requests);
+                self.longest_item_lens = ConstraintSizes::calculate(
+                    self.scoped_branches(),
+                    self.date_style,
+                    self.config.max_name_width(),
+                );
+            }
+            Err(std::sync::mpsc::TryRecvError::Empty) => {}
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => self.pull_request_loader = None,
+        }
+    }
+
+    /// Loads the details of one branch right away, e.g. one that's just been made.
+    fn load_details_now(&mut self, index: usize) {
+        if let Ok(git) = self.repo.open() {
+            self.repo.branches[index].load_details(
+                &git,
+                self.repo.age_base,
+                self.repo.head,
+                self.config.max_message_width(),
+                self.config.date_format(),
+            );
+        }
+    }
+
+    /// Selects this branch, if it's shown.
+    fn select_branch_named(&mut self, name: &str) {
+        let row = self
+            .filtered_indices
+            .iter()
+            .position(|&i| self.repo.branches[i].full_name == name);
+        if let Some(row) = row {
+            self.select_row(row);
+        }
+    }
+
+    /// Starts fetching the default remote in the background, unless that's already happening.
+    fn start_fetch(&mut self) {
+        if self.fetch.is_some() {
+            return;
+        }
+        let Some(remote) = self.repo.default_remote() else {
+            self.notice = Some("There's no remote to fetch from".to_owned());
+            return;
+        };
+        self.notice = None;
+        self.fetch = Some(Fetch::start(self.repo.git_dir.clone(), remote));
+    }
+
+    /// If the fetch has finished, shows how it went and picks up the new remote-tracking branches.
+    fn check_fetch(&mut self) {
+        let Some(fetch) = &self.fetch else {
+            return;
+        };
+        // Reading the branches again under a dialog could change what it's about,
+        // so the result waits until the dialog's closed.
+        if !matches!(self.mode, Mode::Browse) {
+            return;
+        }
+        let result = match fetch.done.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => return,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                Err(git2::Error::from_str("the fetch stopped unexpectedly"))
+            }
+        };
+        let remote = self.fetch.take().unwrap().remote;
+        let result = result
+            .map_err(anyhow::Error::from)
+            .and_then(|()| self.reload_branches());
+        match result {
+            Ok(()) => {
+                self.report_success(format!("Fetched {remote}"));
+            }
+            Err(e) => self.notice = Some(format!("Couldn't fetch {remote}: {e:#}")),
+        }
+    }
+
+    /// `Repo::read_local_branch`, with the name shortened like the rest.
+    fn read_local_branch(&self, name: &str) -> Result<Branch> {
+        let mut branch = self.repo.read_local_branch(name)?;
+        branch.name = self.config.abbreviate(name);
+        Ok(branch)
+    }
+
+    /// Reads all the branches again, keeping the same one selected.
+    fn reload_branches(&mut self) -> Result<()> {
+        let git = self.repo.open()?;
+        let mut repo = read_branches(&git, &self.config, &self.exclude, self.author.as_deref())?;
+        repo.sort_branches(self.sort_mode, self.config.pin_special_branches);
+        let selected = self.selected_branch().map(|b| b.full_name.clone());
+        self.repo = repo;
+        self.repo.set_pull_requests(&self.pull_requests);
+        self.preview_cache.clear();
+        let names: std::collections::HashSet<&str> = self
+            .repo
+            .branches
+            .iter()
+            .filter(|b| b.kind == RefKind::Local)
+            .map(|b| b.full_name.as_str())
+            .collect();
+        self.marked.retain(|name| names.contains(name.as_str()));
+        self.refilter();
+        if let Some(selected) = selected {
+            self.select_branch_named(&selected);
+        }
+        self.start_loading_details();
+        Ok(())
+    }
+
+    /// Where the selected branch is in `repo.branches`.
+    fn selected_index(&self) -> Option<usize> {
+        // The selection may be stale if the branch list changed under it.
+        let i = *self.filtered_indices.get(self.state.selected()?)?;
+        (i < self.repo.branches.len()).then_some(i)
+    }
+
+    fn selected_branch(&self) -> Option<&Branch> {
+        self.repo.branches.get(self.selected_index()?)
+    }
+
+    /// Local branches are in scope unless they're hidden special branches,
+    /// remote ones depend on the `RemoteScope`.
+    fn in_scope(&self, branch: &Branch) -> bool {
+        if (branch.kind == RefKind::Tag) != self.show_tags {
+            return false;
+        }
+        if self.hide_special && self.repo.is_special(branch) {
+            return false;
+        }
+        match (branch.kind, &self.remote_scope) {
+            (RefKind::Local | RefKind::Tag, _) => true,
+            (RefKind::Remote, RemoteScope::LocalOnly) => false,
+            (RefKind::Remote, RemoteScope::All) => true,
+            (RefKind::Remote, RemoteScope::Only(remote)) => branch.remote.as_ref() == Some(remote),
+        }
+    }
+
+    fn scoped_branches(&self) -> impl Iterator<Item = &Branch> + Clone {
+        self.repo.branches.iter().filter(|b| self.in_scope(b))
+    }
+
+    /// Shows the hash column after the name column, or hides it (unless it's the only one).
+    fn toggle_hash_column(&mut self) {
+        match self.columns.iter().position(|&c| c == Column::Hash) {
+            Some(_) if self.columns.len() == 1 => {
+                self.notice = Some("The hash is the only column, keeping it".to_owned());
+            }
+            Some(i) => {
+                self.columns.remove(i);
+            }
+            None => self.columns.insert(1.min(self.columns.len()), Column::Hash),
+        }
+    }
+
+    fn toggle_date_style(&mut self) {
+        self.date_style = self.date_style.toggle();
+        // Absolute and relative dates are different widths.
+        self.refilter();
+    }
+
+    fn toggle_tags(&mut self) {
+        self.show_tags = !self.show_tags;
+        // Branch namespaces probably aren't tag namespaces.
+        self.namespace_filter = None;
+        self.refilter();
+        self.select_row(0);
+    }
+
+    fn set_sort_mode(&mut self, sort_mode: SortMode) {
+        let selected = self.selected_branch().map(|b| b.full_name.clone());
+        self.sort_mode = sort_mode;
+        self.repo
+            .sort_branches(self.sort_mode, self.config.pin_special_branches);
+        self.refilter();
+        // Stay on the same branch wherever it's moved to, rather than on the same row.
+        if let Some(selected) = selected {
+            self.select_branch_named(&selected);
+        }
+    }
+
+    /// For the columns there's a `SortMode` for.
+    fn sort_by_column(&mut self) {
+        let column = self
+            .state
+            .selected_column()
+            .and_then(|i| self.shown_columns.get(i));
+        let sort_mode = match column {
+            Some(Column::Name) => SortMode::Alphabetical,
+            Some(Column::Date) => SortMode::Recent,
+            Some(Column::AheadBehind) => SortMode::MostAhead,
+            Some(column) => {
+                let header = column.header(self.date_mode);
+                self.notice = Some(format!("Can't sort by {header}, only name, date or ↑↓"));
+                return;
+            }
+            None => {
+                self.notice = Some("Highlight a column to sort by first".to_owned());
+                return;
+            }
+        };
+        self.set_sort_mode(sort_mode);
+    }
+
+    /// Steps from local branches only, to every remote's branches, to each remote's in turn
+    /// (if there's more than one), and back to local only.
+    fn cycle_remote_scope(&mut self) {
+        let remotes = &self.repo.remotes;
+        if remotes.is_empty() {
+            self.notice = Some("This repo has no remotes".to_owned());
+            return;
+        }
+        let next_remote = |current: Option<&String>| {
+            let next = match current {
+                None => remotes.first(),
+                Some(current) => remotes.iter().skip_while(|r| *r != current).nth(1),
+            };
+            match next {
+                Some(remote) if remotes.len() > 1 => RemoteScope::Only(remote.clone()),
+                _ => RemoteScope::LocalOnly,
+            }
+        };
+        self.remote_scope = match &self.remote_scope {
+            RemoteScope::LocalOnly => RemoteScope::All,
+            RemoteScope::All => next_remote(None),
+            RemoteScope::Only(current) => next_remote(Some(current)),
+        };
+        // The namespaces on offer depend on which branches are in scope.
+        self.namespace_filter = None;
+        self.refilter();
+    }
+
+    /// Steps the namespace filter through each namespace, biggest first, then back to showing everything.
+    fn cycle_namespace_filter(&mut self) {
+        let namespaces = namespace_counts(self.scoped_branches());
+        let next = match &self.namespace_filter {
+            None => namespaces.first(),
+            Some(current) => namespaces.iter().skip_while(|(ns, _)| ns != current).nth(1),
+        };
+        self.namespace_filter = next.map(|(ns, _)| ns.to_string());
+        self.refilter();
+    }
+
+    /// Recomputes which branches are visible after the filter changed.
+    fn refilter(&mut self) {
+        self.longest_item_lens = ConstraintSizes::calculate(
+            self.scoped_branches(),
+            self.date_style,
+            self.config.max_name_width(),
+        );
+        let filtered_indices: Vec<usize> = self
+            .repo
+            .branches
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| self.in_scope(b))
+            .filter(|(_, b)| match &self.namespace_filter {
+                Some(namespace) => b.namespace() == Some(namespace.as_str()),
+                None => true,
+            })
+            .filter(|(_, b)| match &self.filter {
+                Some(query) => {
+                    fuzzy_match(query, &b.name).is_some()
+                        || (self.filter_messages
+                            && b.last_commit
+                                .as_ref()
+                                .is_some_and(|c| substring_match(query, &c.msg).is_some()))
+                }
+                None => true,
+            })
+            .map(|(i, _)| i)
+            .collect();
+        if filtered_indices != self.filtered_indices {
+            // Whatever the table was scrolled to was for other rows. Starting from the
+            // top again, it scrolls only as far as it needs to for the selection, rather
+            // than leaving rows above it hidden and the space below it empty.
+            *self.state.offset_mut() = 0;
+            self.filtered_indices = filtered_indices;
+        }
+        self.scroll_state = self
+            .scroll_state
+            .content_length(self.filtered_indices.len().saturating_sub(1) * ITEM_HEIGHT);
+        self.clamp_selection();
+    }
+
+    /// Keeps the selected row inside the visible list, and the scrollbar on it,
+    /// so a list that shrank never leaves the selection dangling past its end.
+    fn clamp_selection(&mut self) {
+        let last = self.filtered_indices.len().saturating_sub(1);
+        match self.state.selected() {
+            // The table drops the selection while it has no rows to show,
+            // so pick it back up once there's something to select again.
+            None if !self.filtered_indices.is_empty() => self.state.select(Some(0)),
+            Some(i) if i > last => self.state.select(Some(last)),
+            _ => {}
+        }
+        let selected = self.state.selected().unwrap_or(0);
+        self.scroll_state = self.scroll_state.position(selected * ITEM_HEIGHT);
+    }
+
+    fn next_row(&mut self) {
+        if self.filtered_indices.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(i) => {
+                if i >= self.filtered_indices.len() - 1 {
+                    if self.wrap_around {
+                        0
+                    } else {
+                        i
+                    }
+                } else {
+                    i + 1
+                }
+            }
+            None => 0,
+        };
+        self.select_row(i);
+    }
+
+    fn prev_row(&mut self) {
+        if self.filtered_indices.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(i) => {
+                if i == 0 {
+                    if self.wrap_around {
+                        self.filtered_indices.len() - 1
+                    } else {
+                        0
+                    }
+                } else {
+                    i - 1
+                }
+            }
+            None => 0,
+        };
+        self.select_row(i);
+    }
+
+    fn first_row(&mut self) {
+        if !self.filtered_indices.is_empty() {
+            self.select_row(0);
+        }
+    }
+
+    fn last_row(&mut self) {
+        if let Some(last) = self.filtered_indices.len().checked_sub(1) {
+            self.select_row(last);
+        }
+    }
+
+    /// Moves down by a screenful of rows, stopping at the last one.
+    fn page_down(&mut self) {
+        let Some(last) = self.filtered_indices.len().checked_sub(1) else {
+            return;
+        };
+        let i = self.state.selected().unwrap_or(0) + self.page_size();
+        self.select_row(i.min(last));
+    }
+
+    fn page_up(&mut self) {
+        if self.filtered_indices.is_empty() {
+            return;
+        }
+        let i = self.state.selected().unwrap_or(0);
+        self.select_row(i.saturating_sub(self.page_size()));
+    }
+
+    /// How many rows fit in the table, as of the last time it was drawn.
+    fn page_size(&self) -> usize {
+        // The header takes up a row.
+        (usize::from(self.table_area.height).saturating_sub(1) / ITEM_HEIGHT).max(1)
+    }
+
+    fn select_row(&mut self, i: usize) {
+        self.state.select(Some(i));
+        self.scroll_state = self.scroll_state.position(i * ITEM_HEIGHT);
+    }
+
+    /// Moves the column (and cell) highlight right, wrapping around.
+    /// Nothing is highlighted until the first column move.
+    fn next_column(&mut self) {
+        if self.shown_columns.is_empty() {
+            return;
+        }
+        let i = match self.state.selected_column() {
+            Some(i) => (i + 1) % self.shown_columns.len(),
+            None => 0,
+        };
+        self.state.select_column(Some(i));
+    }
+
+    fn prev_column(&mut self) {
+        if self.shown_columns.is_empty() {
+            return;
+        }
+        let i = match self.state.selected_column() {
+            Some(0) | None => self.shown_columns.len() - 1,
+            Some(i) => i - 1,
         };
-        self.state.select(Some(i));
-        self.scroll_state = self.scroll_state.position(i * ITEM_HEIGHT);
+        self.state.select_column(Some(i));
     }
 
-    fn prev_row(&mut self) {
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.repo.branches.len() - 1
-                } else {
-                    i - 1
-                }
-            }
-            None => 0,
+    /// Drops columns, least useful first, until the rest fit in `width`
+    /// (with room for a useful amount of the commit message). The name never gets dropped,
+    /// and without it, the last column left stays however narrow the screen is.
+    fn fit_columns(&self, width: u16) -> Vec<Column> {
+        const MIN_MSG_WIDTH: u16 = 20;
+        const DROP_ORDER: [Column; 7] = [
+            Column::Author,
+            Column::Hash,
+            Column::Commits,
+            Column::PullRequest,
+            Column::Date,
+            Column::Msg,
+            Column::AheadBehind,
+        ];
+        // The selection bar on the left, and the scrollbar on the right.
+        let chrome_width = 3 + 1;
+        let needed = |columns: &[Column]| -> u16 {
+            let spacing = columns.len().saturating_sub(1) as u16;
+            let content: u16 = columns
+                .iter()
+                .map(|column| match column {
+                    Column::Msg => MIN_MSG_WIDTH,
+                    _ => column.width(self.longest_item_lens, self.date_mode),
+                })
+                .sum();
+            chrome_width + spacing + content
         };
-        self.state.select(Some(i));
-        self.scroll_state = self.scroll_state.position(i * ITEM_HEIGHT);
+        let mut columns = self.columns.clone();
+        for dropped in DROP_ORDER {
+            if needed(&columns) <= width {
+                break;
+            }
+            let rest: Vec<Column> = columns.iter().copied().filter(|&c| c != dropped).collect();
+            if rest.is_empty() {
+                break;
+            }
+            columns = rest;
+        }
+        columns
     }
 
     fn render_table(&mut self, frame: &mut Frame, area: Rect) {
+        self.table_area = area;
+        if self.scoped_branches().next().is_none() {
+            let message = if self.show_tags {
+                "No tags found"
+            } else {
+                "No local branches found"
+            };
+            return self.render_no_branches(frame, area, message);
+        }
+        if self.filtered_indices.is_empty() {
+            let message = match &self.filter {
+                Some(query) if self.filter_messages => {
+                    format!("No branches or messages match '{query}'")
+                }
+                Some(query) => format!("No branches match '{query}'"),
+                None => "Every branch is hidden".to_owned(),
+            };
+            return self.render_no_branches(frame, area, &message);
+        }
+        // The header, then the rows.
+        let height = 1 + self.filtered_indices.len() * ITEM_HEIGHT;
+        let area = match u16::try_from(height) {
+            Ok(height) if self.config.center_table && height < area.height => {
+                frame.render_widget(Block::new().bg(self.colors.buffer_bg), area);
+                centered(area, area.width, height)
+            }
+            _ => area,
+        };
+        self.table_area = area;
+        // If the terminal got taller, scroll back up so the rows fill it again,
+        // rather than leaving rows above hidden and the space below empty.
+        let max_offset = self.filtered_indices.len().saturating_sub(self.page_size());
+        if self.state.offset() > max_offset {
+            *self.state.offset_mut() = max_offset;
+        }
+        self.scroll_state = self
+            .scroll_state
+            .viewport_content_length(self.page_size() * ITEM_HEIGHT);
+        self.shown_columns = self.fit_columns(area.width);
+        if let Some(i) = self.state.selected_column() {
+            if i >= self.shown_columns.len() {
+                self.state
+                    .select_column(self.shown_columns.len().checked_sub(1));
+            }
+        }
         let header_style = Style::default()
             .fg(self.colors.header_fg)
             .bg(self.colors.header_bg);
@@ -320,38 +3383,76 @@ impl App {
             .add_modifier(Modifier::REVERSED)
             .fg(self.colors.selected_cell_style_fg);
 
-        let header = ["Name", "Last commit msg", "Last commit date"]
-            .into_iter()
-            .map(Cell::from)
+        let header = self
+            .shown_columns
+            .iter()
+            .map(|column| match column {
+                Column::Name if self.show_tags => Cell::from("Tag"),
+                _ => Cell::from(column.header(self.date_mode)),
+            })
             .collect::<Row>()
             .style(header_style)
             .height(1);
-        let rows = self.repo.branches.iter().map(|data| {
-            let is_special_branch = SPECIAL_BRANCHES.contains(&data.name.as_str());
-            let color = if is_special_branch {
-                self.colors.unusual_row_color
+        let now = jiff::Timestamp::now().as_second();
+        let rows = self.filtered_indices.iter().map(|&i| {
+            let data = &self.repo.branches[i];
+            let mut style = if self.repo.is_special(data) {
+                Style::new()
+                    .bg(self.colors.unusual_row_color)
+                    .add_modifier(self.colors.unusual_row_modifier)
             } else {
-                self.colors.normal_row_color
+                Style::new().bg(self.colors.divergence_color(data.ahead_behind))
+            };
+            style = match data.kind {
+                _ if data.broken.is_some() => {
+                    style.fg(self.colors.warning_fg).add_modifier(Modifier::DIM)
+                }
+                RefKind::Local | RefKind::Tag => style.fg(self.colors.row_fg),
+                RefKind::Remote => style
+                    .fg(self.colors.remote_row_fg)
+                    .add_modifier(self.colors.remote_row_modifier),
             };
-            let item = data.ref_array();
-            item.into_iter()
-                .map(|content| {
-                    let text = Text::from(content);
-                    Cell::from(text)
+            if data.merged {
+                // Nothing left on it that HEAD doesn't have, so probably safe to delete.
+                style = style.add_modifier(Modifier::DIM);
+            }
+            self.shown_columns
+                .iter()
+                .map(|&column| match column {
+                    Column::Name => Cell::from(self.name_cell(data)),
+                    Column::Msg if self.filter_messages && self.filter.is_some() => {
+                        Cell::from(self.msg_cell(data))
+                    }
+                    Column::Date if self.config.fade_old_dates => {
+                        let text = Text::from(column.cell(data, self.date_mode, self.date_style));
+                        let time = match self.date_mode {
+                            DateMode::LastCommit => data.last_commit.as_ref().map(|c| &c.time),
+                            DateMode::BranchAge => data.age.as_ref(),
+                        };
+                        match time {
+                            Some(time) => Cell::from(
+                                text.fg(self.colors.date_color(now - time.raw.seconds())),
+                            ),
+                            None => Cell::from(text),
+                        }
+                    }
+                    _ => Cell::from(Text::from(column.cell(
+                        data,
+                        self.date_mode,
+                        self.date_style,
+                    ))),
                 })
                 .collect::<Row>()
-                .style(Style::new().fg(self.colors.row_fg).bg(color))
+                .style(style)
                 .height(ITEM_HEIGHT.try_into().unwrap())
         });
         let bar = " > ";
         let t = Table::new(
             rows,
-            [
-                // + 1 is for padding.
-                Constraint::Length(self.longest_item_lens.name + 1),
-                Constraint::Max(self.longest_item_lens.msg + 1),
-                Constraint::Fill(self.longest_item_lens.date),
-            ],
+            self.shown_columns.iter().enumerate().map(|(i, column)| {
+                let is_last = i == self.shown_columns.len() - 1;
+                column.constraint(self.longest_item_lens, self.date_mode, is_last)
+            }),
         )
         .header(header)
         .row_highlight_style(selected_row_style)
@@ -363,6 +3464,91 @@ impl App {
         frame.render_stateful_widget(t, area, &mut self.state);
     }
 
+    /// Shown instead of the table when there are no rows, e.g. in a freshly `git init`ed repo.
+    fn render_no_branches(&self, frame: &mut Frame, area: Rect, message: &str) {
+        frame.render_widget(Block::new().bg(self.colors.buffer_bg), area);
+        let message = Paragraph::new(message)
+            .style(Style::new().fg(self.colors.row_fg))
+            .centered();
+        frame.render_widget(message, centered(area, area.width, 1));
+    }
+
+    /// The branch name, marked if it's checked out, with any filter matches picked out.
+    fn name_cell(&self, branch: &Branch) -> Line<'static> {
+        let mut spans = Vec::new();
+        if self.is_marked(branch) {
+            spans.push(Span::styled(
+                MARKED_MARKER,
+                Style::new().fg(self.colors.warning_fg).bold(),
+            ));
+        } else if branch.is_current {
+            spans.push(Span::styled(
+                CURRENT_BRANCH_MARKER,
+                Style::new().fg(self.colors.current_branch_fg),
+            ));
+        } else if branch.worktree.is_some() {
+            spans.push(Span::styled(
+                WORKTREE_MARKER,
+                Style::new().fg(self.colors.current_branch_fg),
+            ));
+        } else {
+            spans.push(Span::raw(" ".repeat(CURRENT_BRANCH_MARKER.chars().count())));
+        }
+        let matched = match &self.filter {
+            Some(query) => fuzzy_match(query, &branch.name).unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let len = branch.name.chars().count();
+        match middle_cut(&branch.name, self.config.max_name_width()) {
+            None => spans.extend(self.highlight_matches(&branch.name, &matched)),
+            Some((head, tail)) => {
+                // Where each match ended up in the shortened name. Any from the part
+                // that was cut out highlight the ellipsis instead.
+                let matched: Vec<usize> = matched
+                    .iter()
+                    .map(|&i| match i {
+                        i if i < head => i,
+                        i if i >= len - tail => i - (len - tail) + head + 1,
+                        _ => head,
+                    })
+                    .collect();
+                let shown = truncate_middle(&branch.name, self.config.max_name_width());
+                spans.extend(self.highlight_matches(&shown, &matched));
+            }
+        }
+        let line = Line::from(spans);
+        if branch.is_current {
+            line.bold()
+        } else if branch.worktree.is_some() {
+            line.italic()
+        } else {
+            line
+        }
+    }
+
+    /// The last commit message, with the part the filter matched highlighted.
+    fn msg_cell(&self, branch: &Branch) -> Line<'static> {
+        let msg = Column::Msg.cell(branch, self.date_mode, self.date_style);
+        let query = self.filter.as_deref().unwrap_or_default();
+        let matched = substring_match(query, &msg).unwrap_or_default();
+        Line::from(self.highlight_matches(&msg, &matched))
+    }
+
+    /// Picks out the chars of `text` that the filter query matched, by position.
+    fn highlight_matches(&self, text: &str, matched: &[usize]) -> Vec<Span<'static>> {
+        let match_style = Style::new().fg(self.colors.filter_match_fg).bold();
+        text.chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if matched.contains(&i) {
+                    Span::styled(c.to_string(), match_style)
+                } else {
+                    Span::raw(c.to_string())
+                }
+            })
+            .collect()
+    }
+
     fn render_scrollbar(&mut self, frame: &mut Frame, area: Rect) {
         frame.render_stateful_widget(
             Scrollbar::default()
@@ -377,56 +3563,620 @@ impl App {
         );
     }
 
-    fn render_footer(&self, frame: &mut Frame, area: Rect) {
-        let info_footer = Paragraph::new(Text::from_iter([
-            "Gday".to_owned(),
-            format!("Repo: {}", self.repo.root),
-        ]))
-        .style(
-            Style::new()
-                .fg(self.colors.row_fg)
-                .bg(self.colors.buffer_bg),
-        )
-        .centered()
-        .block(
-            Block::bordered()
-                .border_type(BorderType::Double)
-                .border_style(Style::new().fg(self.colors.footer_border_color)),
-        );
+    /// The latest commits on the selected branch.
+    fn render_preview(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(Style::new().fg(self.colors.footer_border_color))
+            .style(
+                Style::new()
+                    .fg(self.colors.row_fg)
+                    .bg(self.colors.buffer_bg),
+            );
+        let Some(branch) = self.selected_branch() else {
+            return frame.render_widget(block, area);
+        };
+        let block = block.title(format!(" {} ", branch.full_name));
+        let refname = branch.refname();
+        if !self.preview_cache.contains_key(&refname) {
+            let commits = self
+                .repo
+                .recent_commits(&refname, PREVIEW_COMMITS)
+                .unwrap_or_default();
+            self.preview_cache.insert(refname.clone(), commits);
+        }
+        let hash_style = Style::new().fg(self.colors.current_branch_fg);
+        let lines: Vec<Line> = self.preview_cache[&refname]
+            .iter()
+            .map(|commit| {
+                Line::from(vec![
+                    Span::styled(commit.hash.clone(), hash_style),
+                    Span::raw(format!(" {} ", commit.msg)),
+                    Span::raw(format!("({})", commit.time)).dim(),
+                ])
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn footer_lines(&self) -> Vec<Line<'static>> {
+        // Out of the shown branches, so with a filter it's also how many matched.
+        let position = match self.state.selected() {
+            Some(row) if !self.filtered_indices.is_empty() => {
+                let noun = if self.show_tags { "Tag" } else { "Branch" };
+                format!("{noun} {}/{}", row + 1, self.filtered_indices.len())
+            }
+            _ if self.show_tags => "No tags shown".to_owned(),
+            _ => "No branches shown".to_owned(),
+        };
+        let mut lines = vec![
+            Line::from("Gday (? for help)"),
+            Line::from(format!(
+                "Repo: {}  {position}  Sorted by: {}",
+                self.repo.root,
+                self.sort_mode.label()
+            )),
+        ];
+        if let Some(branch) = self.selected_branch().filter(|b| b.kind == RefKind::Local) {
+            lines.push(Line::from(match &branch.upstream {
+                Some(upstream) => format!("Tracking: {upstream}"),
+                None => "No upstream".to_owned(),
+            }));
+        }
+        if let Some(description) = self.selected_branch().and_then(|b| b.description.as_ref()) {
+            // Only the first line fits, the rest is usually detail anyway.
+            let mut lines_of = description.lines();
+            let first = lines_of.next().unwrap_or_default();
+            let more = if lines_of.next().is_some() {
+                " …"
+            } else {
+                ""
+            };
+            lines.push(Line::from(format!("{first}{more}")).italic());
+        }
+        if self.selected_branch().is_some_and(|b| b.merged) {
+            let into = self
+                .repo
+                .branches
+                .iter()
+                .find(|b| b.is_current)
+                .map_or("HEAD", |b| b.full_name.as_str());
+            lines.push(Line::from(format!("Merged into {into}")));
+        }
+        if let Some(path) = self.selected_branch().and_then(|b| b.worktree.as_ref()) {
+            lines.push(Line::from(format!(
+                "{WORKTREE_MARKER}Checked out in the worktree at {}",
+                path.display()
+            )));
+        }
+        if let Some(branch) = self.selected_branch() {
+            if let Some(error) = &branch.broken {
+                lines.push(
+                    Line::from(format!(
+                        "{} doesn't point at a commit, {error}",
+                        branch.full_name
+                    ))
+                    .fg(self.colors.warning_fg),
+                );
+            }
+        }
+        if let Some(fetch) = &self.fetch {
+            lines.push(
+                Line::from(format!("{} Fetching {}...", fetch.spinner(), fetch.remote))
+                    .fg(self.colors.notice_fg),
+            );
+        }
+        if let Some(notice) = &self.notice {
+            lines.push(Line::from(notice.clone()).fg(self.colors.notice_fg));
+        }
+        if let Some(filter) = &self.filter {
+            lines.push(Line::from(vec![
+                Span::from("Filter: /").bold(),
+                Span::from(format!("{filter}_")),
+                Span::from(format!("  ({} matching)", self.filtered_indices.len())).dim(),
+                Span::from(if self.filter_messages {
+                    "  Tab: names only"
+                } else {
+                    "  Tab: names and messages"
+                })
+                .dim(),
+            ]));
+        }
+        if let Some((typed, _)) = &self.type_ahead {
+            lines.push(Line::from(vec![
+                Span::from("Jump to: '").bold(),
+                Span::from(format!("{typed}_")),
+            ]));
+        }
+        match &self.remote_scope {
+            _ if self.show_tags => lines.push(Line::from("Showing tags (Tab for branches)")),
+            RemoteScope::LocalOnly => {}
+            RemoteScope::All => lines.push(Line::from("Showing branches from all remotes")),
+            RemoteScope::Only(remote) => lines.push(Line::from(format!(
+                "Showing branches from remote: {remote}"
+            ))),
+        }
+        let mut hidden = Vec::new();
+        if self.repo.excluded > 0 {
+            hidden.push(format!("{} by --exclude", self.repo.excluded));
+        }
+        if self.repo.by_others > 0 {
+            hidden.push(format!("{} by others (--mine)", self.repo.by_others));
+        }
+        if self.hide_special {
+            hidden.push("special branches (H to show)".to_owned());
+        }
+        if !hidden.is_empty() {
+            lines.push(Line::from(format!("Hidden: {}", hidden.join(", "))).dim());
+        }
+        let namespaces = namespace_counts(self.scoped_branches());
+        if !namespaces.is_empty() {
+            let mut spans = Vec::new();
+            for (i, (namespace, count)) in namespaces.into_iter().enumerate() {
+                if i > 0 {
+                    spans.push(", ".into());
+                }
+                let span = Span::from(format!("{namespace}: {count}"));
+                if self.namespace_filter.as_deref() == Some(namespace) {
+                    spans.push(span.bold().underlined());
+                } else {
+                    spans.push(span);
+                }
+            }
+            lines.push(Line::from(spans));
+        }
+        lines
+    }
+
+    fn render_footer(&self, frame: &mut Frame, area: Rect, lines: Vec<Line<'static>>) {
+        let info_footer = Paragraph::new(Text::from(lines))
+            .style(
+                Style::new()
+                    .fg(self.colors.row_fg)
+                    .bg(self.colors.buffer_bg),
+            )
+            .centered()
+            .block(
+                Block::bordered()
+                    .border_type(BorderType::Double)
+                    .border_style(Style::new().fg(self.colors.footer_border_color)),
+            );
         frame.render_widget(info_footer, area);
     }
+
+    /// Asks whether to carry uncommitted changes over to the new branch, stash them, or stay put.
+    fn render_confirm_dirty(&self, frame: &mut Frame, paths: &[String]) {
+        const MAX_PATHS_SHOWN: usize = 5;
+        let mut lines = vec![Line::from("You have uncommitted changes to:")];
+        for path in paths.iter().take(MAX_PATHS_SHOWN) {
+            lines.push(Line::from(format!("  {path}")).fg(self.colors.warning_fg));
+        }
+        if paths.len() > MAX_PATHS_SHOWN {
+            let more = paths.len() - MAX_PATHS_SHOWN;
+            lines.push(Line::from(format!("  ...and {more} more")).dim());
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from("y: switch anyway   s: stash, then switch   n: cancel").bold());
+        self.render_dialog(frame, "Switch branches?", lines);
+    }
+
+    fn render_confirm_delete(&self, frame: &mut Frame, name: &str, merged: bool) {
+        let merged = if merged {
+            Line::from("It's fully merged into HEAD.")
+        } else {
+            Line::from("It's NOT merged into HEAD, so its commits may be lost.")
+                .fg(self.colors.warning_fg)
+        };
+        let lines = vec![
+            Line::from(format!("Delete branch {name}?")),
+            merged,
+            Line::from(""),
+            Line::from("y: delete   n: cancel").bold(),
+        ];
+        self.render_dialog(frame, "Delete branch?", lines);
+    }
+
+    fn render_confirm_bulk_delete(
+        &self,
+        frame: &mut Frame,
+        branches: &[(String, bool)],
+        skipped: usize,
+    ) {
+        const MAX_BRANCHES_SHOWN: usize = 10;
+        let mut lines = vec![Line::from(format!(
+            "Delete {}?",
+            count_branches(branches.len())
+        ))];
+        for (name, merged) in branches.iter().take(MAX_BRANCHES_SHOWN) {
+            lines.push(if *merged {
+                Line::from(format!("  {name}"))
+            } else {
+                Line::from(format!("  {name} (NOT merged into HEAD)")).fg(self.colors.warning_fg)
+            });
+        }
+        if branches.len() > MAX_BRANCHES_SHOWN {
+            let more = branches.len() - MAX_BRANCHES_SHOWN;
+            lines.push(Line::from(format!("  ...and {more} more")).dim());
+        }
+        if skipped > 0 {
+            lines.push(Line::from(format!(
+                "Skipping {skipped} that are protected, checked out, or remote."
+            )));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from("y: delete them all   n: cancel").bold());
+        self.render_dialog(frame, "Delete branches?", lines);
+    }
+
+    /// A box of text floating over the middle of the screen.
+    fn render_dialog(&self, frame: &mut Frame, title: &str, lines: Vec<Line>) {
+        let area = centered(frame.area(), 60, lines.len() as u16 + 2);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .style(
+                    Style::new()
+                        .fg(self.colors.row_fg)
+                        .bg(self.colors.buffer_bg),
+                )
+                .block(
+                    Block::bordered()
+                        .title(format!(" {title} "))
+                        .border_type(BorderType::Rounded)
+                        .border_style(Style::new().fg(self.colors.footer_border_color)),
+                ),
+            area,
+        );
+    }
+
+    fn render_in_other_worktree(&self, frame: &mut Frame, path: &std::path::Path) {
+        let name = self
+            .selected_branch()
+            .map(|b| b.full_name.as_str())
+            .unwrap_or_default();
+        let lines = vec![
+            Line::from(format!(
+                "{name} is already checked out in another worktree:"
+            )),
+            Line::from(format!("  {}", path.display())).fg(self.colors.warning_fg),
+            Line::from(""),
+            Line::from("p: print its path and quit (for cd)   n: cancel").bold(),
+        ];
+        self.render_dialog(frame, "Can't switch here", lines);
+    }
+
+    fn render_commit(
+        &self,
+        frame: &mut Frame,
+        commit: &git_branch_picker::FullCommit,
+        scroll: u16,
+    ) {
+        let area = commit_area(frame.area());
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            commit_paragraph(commit)
+                .scroll((scroll, 0))
+                .style(
+                    Style::new()
+                        .fg(self.colors.row_fg)
+                        .bg(self.colors.buffer_bg),
+                )
+                .block(
+                    Block::bordered()
+                        .title(" Commit (j/k to scroll, Esc to close) ")
+                        .border_type(BorderType::Rounded)
+                        .border_style(Style::new().fg(self.colors.footer_border_color)),
+                ),
+            area,
+        );
+    }
+
+    fn render_confirm_special(&self, frame: &mut Frame) {
+        let name = self
+            .selected_branch()
+            .map(|b| b.full_name.as_str())
+            .unwrap_or_default();
+        let lines = vec![
+            Line::from(format!("Really switch to {name}?")),
+            Line::from(""),
+            Line::from("y: switch   n: cancel").bold(),
+        ];
+        self.render_dialog(frame, "Switch branches?", lines);
+    }
+
+    fn render_help(&self, frame: &mut Frame) {
+        let keys = Action::ALL.map(|action| self.keymap.keys_for(action));
+        let key_width = keys.iter().map(|k| k.width()).max().unwrap_or(0);
+        let lines = Action::ALL
+            .iter()
+            .zip(keys)
+            .map(|(action, keys)| {
+                Line::from(vec![
+                    Span::from(format!(" {keys:key_width$}  ")).bold(),
+                    Span::from(match action {
+                        Action::Checkout => self.config.on_enter.description(),
+                        _ => action.description(),
+                    }),
+                ])
+            })
+            .collect();
+        self.render_dialog(frame, "Keys (? or Esc to close)", lines);
+    }
+
+    /// A text input box floating over the middle of the screen.
+    fn render_prompt(&self, frame: &mut Frame, title: &str, prompt: &Prompt) {
+        let mut lines = vec![Line::from(format!("> {}", prompt.input))];
+        // Show how the name will actually look in the table, if it'll be shortened.
+        let shown_as = self.config.abbreviate(prompt.input.trim());
+        if shown_as != prompt.input.trim() {
+            lines.push(Line::from(format!("Shown as: {shown_as}")).dim());
+        }
+        lines.push(match &prompt.error {
+            Some(error) => Line::from(error.as_str()).fg(self.colors.error_fg),
+            None => Line::from("Enter to confirm, Esc to cancel").dim(),
+        });
+        let area = centered(frame.area(), 60, lines.len() as u16 + 2);
+        let text = Text::from(lines);
+        let block = Block::bordered()
+            .title(format!(" {title} "))
+            .border_type(BorderType::Rounded)
+            .border_style(Style::new().fg(self.colors.footer_border_color));
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(text)
+                .style(
+                    Style::new()
+                        .fg(self.colors.row_fg)
+                        .bg(self.colors.buffer_bg),
+                )
+                .block(block),
+            area,
+        );
+        let cursor_x = inner.x + 2 + prompt.input.width() as u16;
+        frame.set_cursor_position((cursor_x.min(inner.right().saturating_sub(1)), inner.y));
+    }
+}
+
+/// If `query` appears in `text` (ignoring case), the positions of the chars in `text` it matched.
+/// Unlike `fuzzy_match` the chars have to be together, or a long message would match most queries.
+fn substring_match(query: &str, text: &str) -> Option<Vec<usize>> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().collect();
+    if query.is_empty() {
+        return Some(Vec::new());
+    }
+    // Compared a char at a time so that positions still line up with `text`,
+    // even for chars that lowercase to more than one.
+    (0..text.len()).find_map(|start| {
+        let mut lowered = text[start..]
+            .iter()
+            .enumerate()
+            .flat_map(|(i, c)| c.to_lowercase().map(move |l| (start + i, l)));
+        let mut matched = Vec::new();
+        for &q in &query {
+            let (i, l) = lowered.next()?;
+            if l != q {
+                return None;
+            }
+            if matched.last() != Some(&i) {
+                matched.push(i);
+            }
+        }
+        Some(matched)
+    })
+}
+
+/// If every char of `query` appears in `name` in order (ignoring case),
+/// returns the positions of the chars in `name` that matched.
+fn fuzzy_match(query: &str, name: &str) -> Option<Vec<usize>> {
+    let mut matched = Vec::new();
+    let mut name_chars = name.chars().enumerate();
+    for q in query.chars().flat_map(char::to_lowercase) {
+        let (i, _) = name_chars.find(|(_, c)| c.to_lowercase().eq(std::iter::once(q)))?;
+        matched.push(i);
+    }
+    Some(matched)
+}
+
+/// Checks `name` could be created as a new local branch,
+/// returning a message for the user if not.
+fn validate_new_branch_name(repo: &Repo, name: &str) -> Result<(), String> {
+    if !git2::Branch::name_is_valid(name).unwrap_or(false) {
+        return Err(format!("'{name}' isn't a valid branch name"));
+    }
+    let git = repo.open().map_err(|e| e.to_string())?;
+    if git.find_branch(name, BranchType::Local).is_ok() {
+        return Err(format!("A branch called '{name}' already exists"));
+    }
+    Ok(())
+}
+
+/// "1 branch", "2 branches" etc.
+fn count_branches(n: usize) -> String {
+    match n {
+        1 => "1 branch".to_owned(),
+        n => format!("{n} branches"),
+    }
+}
+
+/// If `name` needs shortening to fit in `max` columns, how many chars to keep
+/// from the start and how many from the end, leaving room for an ellipsis in between.
+fn middle_cut(name: &str, max: usize) -> Option<(usize, usize)> {
+    if name.width() <= max {
+        return None;
+    }
+    // As many chars as fit in `width` columns, and how wide they turned out
+    // (wide chars can leave a column spare).
+    let fitting = |chars: &mut dyn Iterator<Item = char>, width: usize| {
+        let mut used = 0;
+        let count = chars
+            .map(|c| c.width().unwrap_or(0))
+            .take_while(|w| {
+                let fits = used + w <= width;
+                if fits {
+                    used += w;
+                }
+                fits
+            })
+            .count();
+        (count, used)
+    };
+    let kept = max.saturating_sub(1);
+    // Both ends matter about as much: the start has the namespace, and the end
+    // usually the ticket number or what the branch is for.
+    let (head, head_width) = fitting(&mut name.chars(), kept / 2);
+    let (tail, _) = fitting(&mut name.chars().rev(), kept - head_width);
+    Some((head, tail))
+}
+
+/// e.g. `achalmers/some-very…-feature-name`: the start (often the namespace)
+/// and end of a long name, without its middle.
+fn truncate_middle(name: &str, max: usize) -> String {
+    let len = name.chars().count();
+    let Some((head, tail)) = middle_cut(name, max) else {
+        return name.to_owned();
+    };
+    let start: String = name.chars().take(head).collect();
+    let end: String = name.chars().skip(len - tail).collect();
+    format!("{start}…{end}")
+}
+
+/// Where `Mode::ShowCommit` goes: a bigger box than `render_dialog`'s,
+/// since commit messages can go on a bit.
+fn commit_area(area: Rect) -> Rect {
+    centered(area, 100, area.height.saturating_sub(4))
+}
+
+/// Laid out like `git show` would, wrapped to fit.
+fn commit_paragraph(commit: &git_branch_picker::FullCommit) -> Paragraph<'static> {
+    let mut lines = vec![
+        Line::from(vec![
+            Span::from("commit ").bold(),
+            Span::from(commit.hash.clone()),
+        ]),
+        Line::from(vec![
+            Span::from("Author: ").bold(),
+            Span::from(commit.author.clone()),
+        ]),
+        Line::from(vec![
+            Span::from("Date:   ").bold(),
+            Span::from(format!(
+                "{} ({})",
+                commit.time.absolute, commit.time.relative
+            )),
+        ]),
+        Line::from(""),
+    ];
+    lines.extend(
+        commit
+            .message
+            .lines()
+            .map(|line| Line::from(line.to_owned())),
+    );
+    Paragraph::new(lines).wrap(Wrap { trim: false })
+}
+
+/// A `width` by `height` rect in the middle of `area`.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 struct ConstraintSizes {
     name: u16,
     msg: u16,
+    author: u16,
+    hash: u16,
+    commits: u16,
+    pull_request: u16,
+    ahead_behind: u16,
     date: u16,
 }
 
 impl ConstraintSizes {
-    fn calculate(items: &[Branch]) -> Self {
+    /// Names longer than `max_name_width` get shortened by `truncate_middle`.
+    fn calculate<'a>(
+        items: impl Iterator<Item = &'a Branch> + Clone,
+        date_style: DateStyle,
+        max_name_width: usize,
+    ) -> Self {
         let name_len = items
-            .iter()
-            .map(|b| b.name.chars().count())
+            .clone()
+            .map(|b| truncate_middle(&b.name, max_name_width).width())
             .max()
             .unwrap_or(0);
         let msg_len = items
-            .iter()
+            .clone()
             .map(|b| {
                 b.last_commit
                     .as_ref()
-                    .map(|c| c.msg.lines().next().unwrap().chars().count())
+                    .map(|c| c.msg.width())
                     .unwrap_or_default()
             })
             .max()
             .unwrap_or(0);
-        let date_len = items
-            .iter()
+        let author_len = items
+            .clone()
+            .map(|b| {
+                b.last_commit
+                    .as_ref()
+                    .map(|c| c.author.width())
+                    .unwrap_or_default()
+            })
+            .max()
+            .unwrap_or(0);
+        let hash_len = items
+            .clone()
             .map(|b| {
                 b.last_commit
                     .as_ref()
-                    .map(|c| c.time.chars().count())
+                    .map(|c| c.short_id.len())
+                    .unwrap_or_default()
+            })
+            .max()
+            .unwrap_or(0);
+        let commits_len = items
+            .clone()
+            .map(|b| {
+                Column::Commits
+                    .cell(b, DateMode::LastCommit, date_style)
+                    .len()
+            })
+            .max()
+            .unwrap_or(0);
+        let pull_request_len = items
+            .clone()
+            .map(|b| {
+                Column::PullRequest
+                    .cell(b, DateMode::LastCommit, date_style)
+                    .len()
+            })
+            .max()
+            .unwrap_or(0);
+        let ahead_behind_len = items
+            .clone()
+            .map(|b| {
+                Column::AheadBehind
+                    .cell(b, DateMode::LastCommit, date_style)
+                    .width()
+            })
+            .max()
+            .unwrap_or(0);
+        let date_len = items
+            .map(|b| {
+                // Wide enough for either date mode, so toggling it doesn't shift the columns.
+                [DateMode::LastCommit, DateMode::BranchAge]
+                    .map(|mode| Column::Date.cell(b, mode, date_style).width())
+                    .into_iter()
+                    .max()
                     .unwrap_or_default()
             })
             .max()
@@ -435,7 +4185,331 @@ impl ConstraintSizes {
         Self {
             name: name_len as u16,
             msg: msg_len as u16,
+            author: author_len as u16,
+            hash: hash_len as u16,
+            commits: commits_len as u16,
+            pull_request: pull_request_len as u16,
+            ahead_behind: ahead_behind_len as u16,
             date: date_len as u16,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git_branch_picker::{Commit, CommitTime};
+
+    fn branch_with_message(name: &str, msg: &str) -> Branch {
+        let time = git2::Time::new(1_700_000_000, 0);
+        Branch {
+            name: name.to_owned(),
+            full_name: name.to_owned(),
+            kind: RefKind::Local,
+            remote: None,
+            upstream: None,
+            description: None,
+            tip: None,
+            broken: None,
+            loaded: true,
+            last_commit: Some(Commit {
+                msg: msg.to_owned(),
+                author: "Jo".to_owned(),
+                short_id: "abcdef1".to_owned(),
+                time: CommitTime::new(time, git_branch_picker::DEFAULT_DATE_FORMAT).unwrap(),
+            }),
+            age: None,
+            unique_commits: None,
+            ahead_behind: None,
+            is_current: false,
+            merged: false,
+            worktree: None,
+            pull_request: None,
+        }
+    }
+
+    #[test]
+    fn wide_characters_take_up_two_columns() {
+        let branches = [
+            branch_with_message("main", "fix"),
+            branch_with_message("日本語", "バグを修正 🎉"),
+        ];
+        let sizes = ConstraintSizes::calculate(branches.iter(), DateStyle::Relative, usize::MAX);
+        // 5 CJK chars, a space and an emoji.
+        assert_eq!(sizes.msg, 13);
+        assert_eq!(sizes.name, 6);
+    }
+
+    #[test]
+    fn wide_names_and_messages_are_cut_to_fit_their_width() {
+        assert_eq!(truncate_middle("日本語のブランチ", 5), "日…チ");
+        assert_eq!(truncate_middle("日本語のブランチ", 10), "日本…ンチ");
+        assert_eq!(git_branch_picker::truncate("バグを修正 🎉", 8), "バグを…");
+        let branches = [branch_with_message("日本語のブランチ", "")];
+        let sizes = ConstraintSizes::calculate(branches.iter(), DateStyle::Relative, 5);
+        assert_eq!(sizes.name, 5);
+    }
+
+    /// A picker over these branches, as if started with these command-line arguments.
+    fn app_with(names: &[&str], args: &[&str]) -> App {
+        let repo = Repo {
+            branches: names
+                .iter()
+                .map(|name| branch_with_message(name, ""))
+                .collect(),
+            root: "~/repo".to_owned(),
+            git_dir: "/nonexistent/.git".into(),
+            special_branches: Vec::new(),
+            remotes: Vec::new(),
+            excluded: 0,
+            by_others: 0,
+            age_base: None,
+            head: None,
+            recently_used: Vec::new(),
+        };
+        let args =
+            Args::parse_from(std::iter::once("git-branch-picker").chain(args.iter().copied()));
+        App::new(repo, Config::default(), &args, None).unwrap()
+    }
+
+    fn press(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            app.handle_key_event(KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    /// Where the scrollbar would be drawn for the row at this index.
+    fn scrolled_to(app: &App, row: usize) -> ScrollbarState {
+        ScrollbarState::new(app.filtered_indices.len().saturating_sub(1) * ITEM_HEIGHT)
+            .position(row * ITEM_HEIGHT)
+    }
+
+    fn binding(s: &str) -> KeyBinding {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn key_bindings_parse_modifiers_and_named_keys() {
+        assert_eq!(
+            binding("ctrl-d"),
+            KeyBinding {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::CONTROL,
+            }
+        );
+        assert_eq!(binding("PageDown").code, KeyCode::PageDown);
+        assert_eq!(binding("space").code, KeyCode::Char(' '));
+        // A lone "-" is the key itself.
+        assert_eq!(binding("-").code, KeyCode::Char('-'));
+        assert_eq!(binding("alt--").code, KeyCode::Char('-'));
+        assert!("hyper-x".parse::<KeyBinding>().is_err());
+        assert!("PgDn".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn shift_with_a_char_binds_the_uppercase_char() {
+        assert_eq!(binding("shift-a"), binding("A"));
+        assert_eq!(binding("ctrl-shift-a"), binding("ctrl-A"));
+        // Without a char, Shift stays a modifier.
+        assert_eq!(binding("shift-Tab").modifiers, KeyModifiers::SHIFT);
+    }
+
+    #[test]
+    fn key_events_match_their_bindings() {
+        // Terminals report Shift along with the uppercase char.
+        let shifted = KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT);
+        assert_eq!(KeyBinding::from_event(shifted), binding("shift-a"));
+        let ctrl_d = KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        assert_eq!(KeyBinding::from_event(ctrl_d), binding("ctrl-d"));
+        let shift_up = KeyEvent::new(KeyCode::Up, KeyModifiers::SHIFT);
+        assert_eq!(KeyBinding::from_event(shift_up), binding("shift-Up"));
+    }
+
+    #[test]
+    fn moving_past_the_last_row_wraps_to_the_first() {
+        let mut app = app_with(&["a", "b", "c"], &[]);
+        press(&mut app, "jj");
+        assert_eq!(app.state.selected(), Some(2));
+        assert_eq!(app.scroll_state, scrolled_to(&app, 2));
+        press(&mut app, "j");
+        assert_eq!(app.state.selected(), Some(0));
+        assert_eq!(app.scroll_state, scrolled_to(&app, 0));
+    }
+
+    #[test]
+    fn moving_before_the_first_row_wraps_to_the_last() {
+        let mut app = app_with(&["a", "b", "c"], &[]);
+        app.update(Action::PrevRow);
+        assert_eq!(app.state.selected(), Some(2));
+        assert_eq!(app.scroll_state, scrolled_to(&app, 2));
+    }
+
+    #[test]
+    fn no_wrap_stops_at_either_end() {
+        let mut app = app_with(&["a", "b", "c"], &["--no-wrap"]);
+        press(&mut app, "jjjj");
+        assert_eq!(app.state.selected(), Some(2));
+        press(&mut app, "kkkk");
+        assert_eq!(app.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn filtering_keeps_the_selection_on_a_matching_row() {
+        let mut app = app_with(&["feature", "fix", "main", "master"], &[]);
+        app.update(Action::LastRow);
+        assert_eq!(app.state.selected(), Some(3));
+        app.update(Action::Filter);
+        press(&mut app, "fix");
+        assert_eq!(app.filtered_indices.len(), 1);
+        assert_eq!(app.state.selected(), Some(0));
+        assert_eq!(app.selected_branch().unwrap().full_name, "fix");
+        assert_eq!(app.scroll_state, scrolled_to(&app, 0));
+    }
+
+    #[test]
+    fn jumping_selects_the_next_branch_starting_with_what_was_typed() {
+        let mut app = app_with(&["fix", "main", "master", "Mint"], &[]);
+        press(&mut app, "'ma");
+        assert_eq!(app.selected_branch().unwrap().full_name, "main");
+        press(&mut app, "s");
+        assert_eq!(app.selected_branch().unwrap().full_name, "master");
+        // A fresh jump with the same letter moves on to the next match, ignoring case.
+        app.type_ahead = None;
+        press(&mut app, "'m");
+        assert_eq!(app.selected_branch().unwrap().full_name, "Mint");
+    }
+
+    #[test]
+    fn unbound_letters_jump_without_the_prefix_key() {
+        let mut app = app_with(&["fix", "main", "master", "zebra"], &[]);
+        // `z` isn't bound to anything, `m` and the rest then carry on the jump.
+        press(&mut app, "z");
+        assert_eq!(app.selected_branch().unwrap().full_name, "zebra");
+        app.type_ahead = None;
+        press(&mut app, "mas");
+        assert_eq!(app.selected_branch().unwrap().full_name, "master");
+        // Bound letters still do what they're bound to.
+        app.type_ahead = None;
+        press(&mut app, "k");
+        assert_eq!(app.selected_branch().unwrap().full_name, "main");
+    }
+
+    #[test]
+    fn switching_branch_exits_with_the_selected_one() {
+        let mut app = app_with(&["a", "b", "c"], &["--print"]);
+        press(&mut app, "j");
+        app.update(Action::Checkout);
+        assert!(app.exit);
+        assert!(app.user_switched_branch);
+        assert_eq!(app.selected_branch().unwrap().full_name, "b");
+    }
+
+    #[test]
+    fn ctrl_c_quits_even_while_typing_a_filter() {
+        let mut app = app_with(&["a", "b", "c"], &[]);
+        app.update(Action::Filter);
+        press(&mut app, "b");
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(app.exit);
+        assert!(app.interrupted);
+        assert_eq!(app.filter.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn replacements_apply_in_the_order_theyre_written() {
+        let config: Config = toml::from_str(
+            r#"
+            [replacements]
+            "team/feature/" = "tf/"
+            "team/" = "t/"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.abbreviate("team/feature/x"), "tf/x");
+        assert_eq!(config.abbreviate("team/fix"), "t/fix");
+    }
+
+    #[test]
+    fn marking_a_tag_doesnt_mark_the_branch_with_its_name() {
+        let mut app = app_with(&["foo", "bar"], &[]);
+        app.repo.branches[1].name = "foo".to_owned();
+        app.repo.branches[1].full_name = "foo".to_owned();
+        app.repo.branches[1].kind = RefKind::Tag;
+        app.update(Action::ToggleTags);
+        app.update(Action::ToggleMark);
+        assert!(app.marked.is_empty());
+        assert!(app.notice.is_some());
+
+        app.update(Action::ToggleTags);
+        app.update(Action::ToggleMark);
+        assert!(app.is_marked(&app.repo.branches[0]));
+        assert!(!app.is_marked(&app.repo.branches[1]));
+    }
+
+    #[test]
+    fn fuzzy_matches_chars_in_order_ignoring_case() {
+        assert_eq!(fuzzy_match("fb", "feature/Bar"), Some(vec![0, 8]));
+        assert_eq!(fuzzy_match("FB", "feature/bar"), Some(vec![0, 8]));
+        assert_eq!(fuzzy_match("", "main"), Some(vec![]));
+        assert_eq!(fuzzy_match("bf", "feature/bar"), None);
+    }
+
+    #[test]
+    fn moving_between_no_columns_does_nothing() {
+        let mut app = app_with(&["main"], &[]);
+        app.shown_columns.clear();
+        app.next_column();
+        app.prev_column();
+        assert_eq!(app.state.selected_column(), None);
+    }
+
+    #[test]
+    fn hiding_the_hash_keeps_it_if_its_the_only_column() {
+        let mut app = app_with(&["main"], &["--columns", "hash"]);
+        app.update(Action::ToggleHash);
+        assert_eq!(app.columns, [Column::Hash]);
+        assert!(app.notice.is_some());
+
+        let mut app = app_with(&["main"], &["--columns", "name,msg"]);
+        app.update(Action::ToggleHash);
+        assert_eq!(app.columns, [Column::Name, Column::Hash, Column::Msg]);
+        app.update(Action::ToggleHash);
+        assert_eq!(app.columns, [Column::Name, Column::Msg]);
+    }
+
+    #[test]
+    fn narrow_screens_drop_the_least_useful_columns_first() {
+        let app = app_with(&["main"], &["--columns", "name,author,hash,msg"]);
+        assert_eq!(
+            app.fit_columns(200),
+            [Column::Name, Column::Author, Column::Hash, Column::Msg]
+        );
+        assert_eq!(app.fit_columns(32), [Column::Name, Column::Msg]);
+        assert_eq!(app.fit_columns(1), [Column::Name]);
+    }
+
+    #[test]
+    fn the_last_column_stays_however_narrow_the_screen() {
+        let app = app_with(&["main"], &["--columns", "msg"]);
+        assert_eq!(app.fit_columns(5), [Column::Msg]);
+        let app = app_with(&["main"], &["--columns", "hash,date"]);
+        assert_eq!(app.fit_columns(1), [Column::Date]);
+    }
+
+    #[test]
+    fn substring_matches_need_the_chars_together() {
+        assert_eq!(substring_match("BUG", "fix a bug"), Some(vec![6, 7, 8]));
+        assert_eq!(substring_match("", "fix a bug"), Some(vec![]));
+        assert_eq!(substring_match("fb", "fix a bug"), None);
+        // 'İ' lowercases to two chars, but is still one position in the text.
+        assert_eq!(substring_match("i̇s", "İs"), Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn long_names_lose_their_middle() {
+        assert_eq!(middle_cut("0123456789", 10), None);
+        assert_eq!(middle_cut("0123456789a", 10), Some((4, 5)));
+        assert_eq!(truncate_middle("main", 10), "main");
+        assert_eq!(truncate_middle("achalmers/long-feature", 11), "achal…ature");
+    }
+}